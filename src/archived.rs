@@ -0,0 +1,356 @@
+use std::{marker::PhantomData, mem};
+
+use rkyv::{
+    api::high::{HighSerializer, HighValidator},
+    bytecheck::CheckBytes,
+    rancor,
+    ser::allocator::ArenaHandle,
+    util::AlignedVec,
+    vec::ArchivedVec,
+    Archive, Archived,
+};
+
+use crate::{
+    batches::{write_compressed, PendingElements, WriteBatches},
+    checkedfile::{BatchReader, BatchWriter, FileHandle, SwapFilePath},
+    compression::{BatchCompressionStats, Compress},
+    error::SwapVecError,
+    serialization::{SerializationError, RKYV_FORMAT_ID},
+    swapvec::SwapVecConfig,
+};
+
+fn archive_error(error: rancor::Error) -> SwapVecError {
+    SwapVecError::SerializationFailed(SerializationError::new(error))
+}
+
+/// A [`SwapVec`](crate::SwapVec) storing its batches archived
+/// with [rkyv], instead of serialized with serde.
+///
+/// Elements can then be read without deserializing them,
+/// as `&Archived<T>` views into the decoded batch,
+/// see [`ArchivedSwapVecIter`].
+/// Useful for fixed-layout records, where deserializing
+/// every element would dominate the read time.
+///
+/// Batches are formed, compressed and written as by [`SwapVec`](crate::SwapVec),
+/// only `serialization`, `background_writer` and `prefetch`
+/// of the configuration are ignored. Byte limits take
+/// `size_of::<T>()` per element, unless a size estimator is set.
+/// ```rust
+/// #[derive(rkyv::Archive, rkyv::Serialize)]
+/// struct Point {
+///     x: u32,
+///     y: u32,
+/// }
+///
+/// let mut bigvec = swapvec::ArchivedSwapVec::default();
+/// bigvec.consume((0..99).map(|i| Point { x: i, y: i * 2 })).unwrap();
+/// let mut sum = 0;
+/// bigvec
+///     .into_batches()
+///     .for_each(|point| sum += point.y.to_native())
+///     .unwrap();
+/// assert_eq!(sum, 9702);
+/// ```
+///
+/// [rkyv]: https://rkyv.org
+pub struct ArchivedSwapVec<T> {
+    tempfile: Option<BatchWriter<FileHandle>>,
+    path: Option<SwapFilePath>,
+    vector: PendingElements<T>,
+    config: SwapVecConfig,
+    // Reused for compressing batches, one per thread
+    compression_buffers: Vec<Vec<u8>>,
+    compression_stats: Vec<BatchCompressionStats>,
+}
+
+impl<T> Default for ArchivedSwapVec<T>
+where
+    T: Archive
+        + for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    fn default() -> Self {
+        Self::with_config(SwapVecConfig::default())
+    }
+}
+
+impl<T> ArchivedSwapVec<T>
+where
+    T: Archive
+        + for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    /// Initialize with non-default configuration.
+    pub fn with_config(config: SwapVecConfig) -> Self {
+        Self {
+            tempfile: None,
+            path: None,
+            vector: PendingElements::default(),
+            config,
+            compression_buffers: Vec::new(),
+            compression_stats: Vec::new(),
+        }
+    }
+
+    /// Set how the memory size of an element in bytes is estimated,
+    /// see [`SwapVec::set_size_estimator`](crate::SwapVec::set_size_estimator).
+    ///
+    /// By default, `size_of::<T>()` is used,
+    /// which does not account for heap allocations.
+    pub fn set_size_estimator(&mut self, estimator: impl Fn(&T) -> usize + Send + Sync + 'static) {
+        self.vector
+            .set_size_estimator(&self.config, Box::new(estimator));
+    }
+
+    /// Give away an entire iterator for consumption.
+    /// Might return an error, due to possibly triggered batch flush (IO).
+    pub fn consume(&mut self, it: impl Iterator<Item = T>) -> Result<(), SwapVecError> {
+        for element in it {
+            self.push(element)?;
+        }
+        Ok(())
+    }
+
+    /// Push a single element.
+    /// Might return an error, due to possibly triggered batch flush (IO).
+    /// Will write at most one batch per insert.
+    pub fn push(&mut self, element: T) -> Result<(), SwapVecError> {
+        self.vector
+            .push(&self.config, element, |_| mem::size_of::<T>());
+        self.write_due_batches()
+    }
+
+    /// Total number of elements, both in the file and in memory.
+    pub fn len(&self) -> usize {
+        self.tempfile.as_ref().map_or(0, |w| w.element_count()) + self.vector.len()
+    }
+
+    /// Returns `true` if no elements have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if enough items have been pushed so that
+    /// the temporary file has been created.
+    pub fn written_to_file(&self) -> bool {
+        self.tempfile.is_some()
+    }
+
+    /// Number of batches written to the temporary file.
+    pub fn batches_written(&self) -> usize {
+        self.tempfile.as_ref().map_or(0, |w| w.batch_count())
+    }
+
+    /// Size before and after compression of every batch written,
+    /// see [`SwapVec::compression_stats`](crate::SwapVec::compression_stats).
+    pub fn compression_stats(&self) -> &[BatchCompressionStats] {
+        &self.compression_stats
+    }
+
+    /// Iterate over the archived batches.
+    /// Elements still in memory are archived as one last batch.
+    pub fn into_batches(self) -> ArchivedSwapVecIter<T> {
+        let (tempfile, mut new_error) = match self.tempfile.map(|w| w.try_into()) {
+            None => (None, None),
+            Some(Ok(reader)) => (Some(reader), None),
+            Some(Err(e)) => (None, Some(SwapVecError::from(e))),
+        };
+        let last_elements = Vec::from(self.vector.into_elements());
+        let last_elements_count = last_elements.len();
+        let last_elements = if last_elements.is_empty() {
+            AlignedVec::new()
+        } else {
+            rkyv::to_bytes::<rancor::Error>(&last_elements).unwrap_or_else(|e| {
+                new_error.get_or_insert(archive_error(e));
+                AlignedVec::new()
+            })
+        };
+        ArchivedSwapVecIter {
+            new_error,
+            tempfile,
+            _path: self.path,
+            last_elements,
+            last_elements_count,
+            batch_index: 0,
+            buffer: AlignedVec::new(),
+            decompressed: Vec::new(),
+            config: self.config,
+            element_type: PhantomData,
+        }
+    }
+}
+
+impl<T> WriteBatches<T> for ArchivedSwapVec<T>
+where
+    T: Archive
+        + for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    fn pending(&self) -> &PendingElements<T> {
+        &self.vector
+    }
+
+    fn config(&self) -> &SwapVecConfig {
+        &self.config
+    }
+
+    fn has_file(&self) -> bool {
+        self.written_to_file()
+    }
+
+    fn write_batch(&mut self) -> Result<(), SwapVecError> {
+        let written_to_file = self.written_to_file();
+        let batches = self
+            .vector
+            .take_batches(&mut self.config, written_to_file, archive_batch)?;
+        if !written_to_file {
+            let (file, path) = self.config.create_tempfile()?;
+            let mut header = self.config.file_header::<T>();
+            header.serialization = RKYV_FORMAT_ID;
+            self.tempfile = Some(BatchWriter::new(file, header)?);
+            self.path = Some(path);
+        }
+        write_compressed(
+            self.tempfile.as_mut().unwrap(),
+            &self.config.compression,
+            &batches,
+            &mut self.compression_buffers,
+            &mut self.compression_stats,
+        )
+    }
+}
+
+fn archive_batch<T>(batch: Vec<T>) -> Result<Vec<u8>, SwapVecError>
+where
+    T: for<'a> rkyv::Serialize<HighSerializer<AlignedVec, ArenaHandle<'a>, rancor::Error>>,
+{
+    let bytes = rkyv::to_bytes::<rancor::Error>(&batch).map_err(archive_error)?;
+    Ok(bytes.into_vec())
+}
+
+/// Batch-wise iterator for [`ArchivedSwapVec`].
+///
+/// Every batch is read, checked and decompressed into
+/// one buffer, and handed out as a slice of archived elements
+/// borrowing from it. Elements are not deserialized.
+///
+/// The iteration ends after the first error.
+///
+/// Dropping the iterator removes the temporary file, if existing.
+pub struct ArchivedSwapVecIter<T: Archive> {
+    new_error: Option<SwapVecError>,
+    tempfile: Option<BatchReader<FileHandle>>,
+    // Removes the temporary file on drop
+    _path: Option<SwapFilePath>,
+    // Elements which have not been written to disk,
+    // treated as one more batch after the file batches.
+    last_elements: AlignedVec,
+    last_elements_count: usize,
+    batch_index: usize,
+    // Decoded batch, aligned for accessing it
+    buffer: AlignedVec,
+    decompressed: Vec<u8>,
+    config: SwapVecConfig,
+    element_type: PhantomData<T>,
+}
+
+impl<T> ArchivedSwapVecIter<T>
+where
+    T: Archive,
+    Archived<T>: for<'a> CheckBytes<HighValidator<'a, rancor::Error>>,
+{
+    /// Number of elements in all batches.
+    pub fn element_count(&self) -> usize {
+        self.tempfile.as_ref().map_or(0, |t| t.element_count()) + self.last_elements_count
+    }
+
+    /// Number of batches, including the elements
+    /// which have not been written to disk.
+    pub fn batch_count(&self) -> usize {
+        self.file_batch_count() + usize::from(self.last_elements_count > 0)
+    }
+
+    fn file_batch_count(&self) -> usize {
+        self.tempfile.as_ref().map_or(0, |t| t.batch_count())
+    }
+
+    /// Read the next batch. The returned elements
+    /// borrow from the iterator, until the next call.
+    /// ```rust
+    /// let mut bigvec = swapvec::ArchivedSwapVec::default();
+    /// bigvec.consume(0..99u64).unwrap();
+    /// let mut batches = bigvec.into_batches();
+    /// while let Some(batch) = batches.next_batch() {
+    ///     for value in batch.unwrap() {
+    ///         assert!(value.to_native() < 99);
+    ///     }
+    /// }
+    /// ```
+    pub fn next_batch(&mut self) -> Option<Result<&[Archived<T>], SwapVecError>> {
+        if let Some(err) = self.new_error.take() {
+            return Some(Err(self.abort(err)));
+        }
+        let batch = self.batch_index;
+        let batch_count = self.batch_count();
+        if batch >= batch_count {
+            return None;
+        }
+        self.batch_index += 1;
+        let bytes = if batch == self.file_batch_count() {
+            &self.last_elements
+        } else {
+            if let Err(err) = self.read_file_batch() {
+                return Some(Err(self.abort(err)));
+            }
+            &self.buffer
+        };
+        match rkyv::access::<ArchivedVec<Archived<T>>, rancor::Error>(bytes) {
+            Ok(elements) => Some(Ok(elements.as_slice())),
+            Err(err) => {
+                // Not `abort()`, `bytes` still borrows from `self`
+                self.batch_index = batch_count;
+                Some(Err(archive_error(err)))
+            }
+        }
+    }
+
+    /// Call `f` for every archived element, in order.
+    /// Stops at the first error.
+    pub fn for_each(&mut self, mut f: impl FnMut(&Archived<T>)) -> Result<(), SwapVecError> {
+        while let Some(batch) = self.next_batch() {
+            batch?.iter().for_each(&mut f);
+        }
+        Ok(())
+    }
+
+    /// Start again with the first batch.
+    pub fn reset(&mut self) {
+        self.batch_index = 0;
+        if let Some(tempfile) = self.tempfile.as_mut() {
+            if let Err(e) = tempfile.reset() {
+                self.new_error = Some(e.into());
+            }
+        }
+    }
+
+    fn read_file_batch(&mut self) -> Result<(), SwapVecError> {
+        let tempfile = self.tempfile.as_mut().unwrap();
+        let bytes = tempfile.read_batch()?.ok_or(SwapVecError::InvalidFormat)?;
+        self.buffer.clear();
+        if self.config.compression.is_none() {
+            self.buffer.extend_from_slice(bytes);
+        } else {
+            self.decompressed.clear();
+            self.config
+                .compression
+                .decompress(bytes, &mut self.decompressed)
+                .map_err(SwapVecError::Decompression)?;
+            self.buffer.extend_from_slice(&self.decompressed);
+        }
+        Ok(())
+    }
+
+    // End the iteration after an error
+    fn abort(&mut self, err: SwapVecError) -> SwapVecError {
+        self.batch_index = self.batch_count();
+        err
+    }
+}
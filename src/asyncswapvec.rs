@@ -0,0 +1,359 @@
+use std::{
+    collections::{vec_deque, VecDeque},
+    future::Future,
+    io::{self, SeekFrom},
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use futures_core::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+
+use crate::{
+    batches::{write_compressed, PendingElements, WriteBatches},
+    checkedfile::{check_frame, BatchInfo, BatchWriter, Checksum, SwapFilePath},
+    compression::BatchCompressionStats,
+    error::SwapVecError,
+    fileformat::FRAME_HEADER_LEN,
+    serialization::Serializer,
+    swapvec::SwapVecConfig,
+    swapveciter::decode_batch,
+};
+
+/// A [`SwapVec`](crate::SwapVec) for async code, writing and reading
+/// its file with tokio, so the executor is never blocked on disk IO.
+///
+/// Uses the same file format, and forms and compresses batches
+/// as [`SwapVec`](crate::SwapVec) does, except for `background_writer`
+/// and `prefetch`, which are ignored. Serialization and compression
+/// happen on the calling task, the file is created on tokio's
+/// blocking threads.
+/// ```rust
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// use futures_util::StreamExt;
+///
+/// let mut bigvec = swapvec::AsyncSwapVec::default();
+/// for i in 0..99 {
+///     bigvec.push(i).await.unwrap();
+/// }
+/// let sum: i32 = bigvec.into_stream().await.map(|v| v.unwrap()).fold(0, |a, b| async move { a + b }).await;
+/// assert_eq!(sum, 4851);
+/// # });
+/// ```
+pub struct AsyncSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    file: Option<File>,
+    path: Option<SwapFilePath>,
+    // Encodes batches in the file format, the bytes
+    // are then taken out and written to `file`.
+    writer: Option<BatchWriter<Vec<u8>>>,
+    // Encoded bytes not written to `file` yet,
+    // e.g. because a push has been cancelled.
+    unwritten: Vec<u8>,
+    vector: PendingElements<T>,
+    config: SwapVecConfig,
+    // Reused for compressing batches, one per thread
+    compression_buffers: Vec<Vec<u8>>,
+    compression_stats: Vec<BatchCompressionStats>,
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> Default for AsyncSwapVec<T> {
+    fn default() -> Self {
+        Self::with_config(SwapVecConfig::default())
+    }
+}
+
+impl<T> AsyncSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    /// Initialize with non-default configuration.
+    pub fn with_config(config: SwapVecConfig) -> Self {
+        Self {
+            file: None,
+            path: None,
+            writer: None,
+            unwritten: Vec::new(),
+            vector: PendingElements::default(),
+            config,
+            compression_buffers: Vec::new(),
+            compression_stats: Vec::new(),
+        }
+    }
+
+    /// Set how the memory size of an element in bytes is estimated,
+    /// see [`SwapVec::set_size_estimator`](crate::SwapVec::set_size_estimator).
+    pub fn set_size_estimator(&mut self, estimator: impl Fn(&T) -> usize + Send + Sync + 'static) {
+        self.vector
+            .set_size_estimator(&self.config, Box::new(estimator));
+    }
+
+    /// Push a single element.
+    /// Might return an error, due to possibly triggered batch flush (IO).
+    /// Will write at most one batch per insert.
+    ///
+    /// Cancelling a push is safe, the element is kept,
+    /// and unwritten parts of the batch are written with the next one.
+    pub async fn push(&mut self, element: T) -> Result<(), SwapVecError> {
+        let serialization = self.config.serialization;
+        self.vector.push(&self.config, element, |element| {
+            serialization.serialized_size(element).unwrap_or(0)
+        });
+        if self.file.is_none() && self.vector.batch_ready(&self.config, false) {
+            self.create_file().await?;
+        }
+        self.write_due_batches()?;
+        self.write_out().await
+    }
+
+    /// Give away an entire iterator for consumption.
+    pub async fn consume(&mut self, it: impl Iterator<Item = T>) -> Result<(), SwapVecError> {
+        for element in it {
+            self.push(element).await?;
+        }
+        Ok(())
+    }
+
+    /// Give away an entire stream for consumption,
+    /// e.g. the records of an upload.
+    pub async fn consume_stream(
+        &mut self,
+        stream: impl Stream<Item = T>,
+    ) -> Result<(), SwapVecError> {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(element) = std::future::poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            self.push(element).await?;
+        }
+        Ok(())
+    }
+
+    /// Total number of elements, both in the file and in memory.
+    pub fn len(&self) -> usize {
+        self.writer.as_ref().map_or(0, |w| w.element_count()) + self.vector.len()
+    }
+
+    /// Returns `true` if no elements have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if enough items have been pushed so that
+    /// the temporary file has been created.
+    pub fn written_to_file(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Number of batches written to the temporary file.
+    pub fn batches_written(&self) -> usize {
+        self.writer.as_ref().map_or(0, |w| w.batch_count())
+    }
+
+    /// Size before and after compression of every batch written,
+    /// see [`SwapVec::compression_stats`](crate::SwapVec::compression_stats).
+    pub fn compression_stats(&self) -> &[BatchCompressionStats] {
+        &self.compression_stats
+    }
+
+    /// Stream all elements, after writing
+    /// outstanding bytes to the file.
+    pub async fn into_stream(mut self) -> AsyncSwapVecIter<T> {
+        let new_error = self.write_out().await.err();
+        let (batch_infos, checksum) = match self.writer.as_ref() {
+            Some(writer) => (writer.batch_infos().to_vec(), writer.checksum()),
+            None => (Vec::new(), Checksum::None),
+        };
+        AsyncSwapVecIter {
+            new_error,
+            file: self.file,
+            reading: None,
+            _path: self.path,
+            batch_infos,
+            checksum,
+            batch_index: 0,
+            current_batch: Vec::new().into_iter(),
+            last_elements: self.vector.into_elements().into_iter(),
+            decompressed: Vec::new(),
+            config: self.config,
+        }
+    }
+
+    // Created before taking any batch, so
+    // cancelling meanwhile loses no elements.
+    async fn create_file(&mut self) -> Result<(), SwapVecError> {
+        let config = self.config.clone();
+        let (file, path) = tokio::task::spawn_blocking(move || config.create_tempfile())
+            .await
+            .map_err(io::Error::other)??;
+        self.file = Some(File::from_std(file.into_file()));
+        self.path = Some(path);
+        Ok(())
+    }
+
+    // Write `unwritten` to the file. Cancel safe,
+    // written bytes are removed after every write.
+    async fn write_out(&mut self) -> Result<(), SwapVecError> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        while !self.unwritten.is_empty() {
+            let written = file.write(&self.unwritten).await?;
+            if written == 0 {
+                return Err(io::Error::from(io::ErrorKind::WriteZero).into());
+            }
+            self.unwritten.drain(..written);
+        }
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+impl<T> WriteBatches<T> for AsyncSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    fn pending(&self) -> &PendingElements<T> {
+        &self.vector
+    }
+
+    fn config(&self) -> &SwapVecConfig {
+        &self.config
+    }
+
+    fn has_file(&self) -> bool {
+        self.written_to_file()
+    }
+
+    // Encode the next batches into `unwritten`,
+    // the file has been created before.
+    fn write_batch(&mut self) -> Result<(), SwapVecError> {
+        let written_to_file = self.written_to_file();
+        let serialization = self.config.serialization;
+        let batches = self
+            .vector
+            .take_batches(&mut self.config, written_to_file, |batch| {
+                Ok(serialization.serialize(&batch)?)
+            })?;
+        if !written_to_file {
+            let header = self.config.file_header::<T>();
+            self.writer = Some(BatchWriter::new(Vec::new(), header)?);
+        }
+        let writer = self.writer.as_mut().unwrap();
+        write_compressed(
+            writer,
+            &self.config.compression,
+            &batches,
+            &mut self.compression_buffers,
+            &mut self.compression_stats,
+        )?;
+        self.unwritten.append(writer.get_mut());
+        Ok(())
+    }
+}
+
+type FrameRead = Pin<Box<dyn Future<Output = (File, Result<Vec<u8>, SwapVecError>)> + Send>>;
+
+// Takes the file and gives it back, so the
+// future does not borrow from the stream.
+async fn read_frame(
+    mut file: File,
+    batch_info: BatchInfo,
+) -> (File, Result<Vec<u8>, SwapVecError>) {
+    let mut frame = vec![0; FRAME_HEADER_LEN as usize + batch_info.bytes];
+    let result = match file.seek(SeekFrom::Start(batch_info.offset)).await {
+        Ok(_) => file.read_exact(&mut frame).await,
+        Err(e) => Err(e),
+    };
+    (file, result.map(|_| frame).map_err(SwapVecError::from))
+}
+
+/// Stream for [`AsyncSwapVec`].
+///
+/// Items might be read from disk,
+/// so every item is wrapped in a `Result`.
+/// The stream ends after the first error.
+///
+/// Dropping the stream removes the temporary file, if existing.
+pub struct AsyncSwapVecIter<T> {
+    new_error: Option<SwapVecError>,
+    // Given to `reading` while a batch is read
+    file: Option<File>,
+    reading: Option<FrameRead>,
+    // Removes the temporary file on drop
+    _path: Option<SwapFilePath>,
+    batch_infos: Vec<BatchInfo>,
+    checksum: Checksum,
+    batch_index: usize,
+    current_batch: std::vec::IntoIter<T>,
+    // Elements which have not been written to disk
+    last_elements: vec_deque::IntoIter<T>,
+    // Reused for decompressing batches
+    decompressed: Vec<u8>,
+    config: SwapVecConfig,
+}
+
+// Elements are never pinned
+impl<T> Unpin for AsyncSwapVecIter<T> {}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> AsyncSwapVecIter<T> {
+    fn decode(&mut self, frame: &[u8]) -> Result<Vec<T>, SwapVecError> {
+        let batch_info = &self.batch_infos[self.batch_index - 1];
+        let payload = check_frame(frame, batch_info, self.checksum)?;
+        decode_batch(&self.config, payload, &mut self.decompressed)
+    }
+
+    // End the stream after an error
+    fn abort(&mut self, err: SwapVecError) -> Poll<Option<Result<T, SwapVecError>>> {
+        self.batch_index = self.batch_infos.len();
+        self.current_batch = Vec::new().into_iter();
+        self.last_elements = VecDeque::new().into_iter();
+        Poll::Ready(Some(Err(err)))
+    }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> Stream for AsyncSwapVecIter<T> {
+    type Item = Result<T, SwapVecError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(err) = this.new_error.take() {
+            return this.abort(err);
+        }
+        loop {
+            if let Some(item) = this.current_batch.next() {
+                return Poll::Ready(Some(Ok(item)));
+            }
+            if let Some(reading) = this.reading.as_mut() {
+                let (file, frame) = ready!(reading.as_mut().poll(cx));
+                this.reading = None;
+                this.file = Some(file);
+                match frame.and_then(|frame| this.decode(&frame)) {
+                    Ok(batch) => this.current_batch = batch.into_iter(),
+                    Err(err) => return this.abort(err),
+                }
+                continue;
+            }
+            if this.batch_index < this.batch_infos.len() {
+                let batch_info = this.batch_infos[this.batch_index].clone();
+                this.batch_index += 1;
+                let file = this.file.take().unwrap();
+                this.reading = Some(Box::pin(read_frame(file, batch_info)));
+                continue;
+            }
+            return Poll::Ready(this.last_elements.next().map(Ok));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Including the batch being read
+        let unread = self.batch_index - usize::from(self.reading.is_some());
+        let file_elements: usize = self.batch_infos[unread..].iter().map(|b| b.elements).sum();
+        let len = self.current_batch.len() + file_elements + self.last_elements.len();
+        (len, Some(len))
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    batches::{write_compressed, SerializedBatch},
+    checkedfile::{BatchInfo, BatchWriter, Checksum, FileHandle},
+    compression::BatchCompressionStats,
+    error::SwapVecError,
+    swapvec::Compression,
+};
+
+enum Job {
+    /// Serialized batches, compressed in parallel
+    Batches(Vec<SerializedBatch>),
+    /// Reply with the batches written so far,
+    /// after all batches sent before.
+    Sync(Sender<(Vec<BatchInfo>, Checksum)>),
+}
+
+/// Returned by the writer thread when it stops,
+/// either because the sender has been dropped,
+/// or because writing a batch failed.
+pub(crate) struct WriterOutcome {
+    pub writer: BatchWriter<FileHandle>,
+    pub stats: Vec<BatchCompressionStats>,
+    pub error: Option<SwapVecError>,
+}
+
+/// Thread compressing and writing batches, so pushing
+/// elements does not wait for the disk.
+///
+/// At most `queue_size` batches wait in the channel,
+/// sending more blocks until the thread catches up.
+pub(crate) struct WriterThread {
+    sender: Option<SyncSender<Job>>,
+    handle: Option<JoinHandle<WriterOutcome>>,
+    batch_count: usize,
+    element_count: usize,
+    // Updated by the thread after every batch
+    bytes_written: Arc<AtomicUsize>,
+}
+
+impl WriterThread {
+    pub fn spawn(
+        mut writer: BatchWriter<FileHandle>,
+        compression: Option<Compression>,
+        queue_size: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        let batch_count = writer.batch_count();
+        let element_count = writer.element_count();
+        let bytes_written = Arc::new(AtomicUsize::new(writer.bytes_written()));
+        let thread_bytes_written = bytes_written.clone();
+        let handle = thread::spawn(move || {
+            let mut stats = Vec::new();
+            let error = Self::run(
+                &mut writer,
+                &compression,
+                receiver,
+                &mut stats,
+                &thread_bytes_written,
+            )
+            .err();
+            WriterOutcome {
+                writer,
+                stats,
+                error,
+            }
+        });
+        Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            batch_count,
+            element_count,
+            bytes_written,
+        }
+    }
+
+    fn run(
+        writer: &mut BatchWriter<FileHandle>,
+        compression: &Option<Compression>,
+        receiver: Receiver<Job>,
+        stats: &mut Vec<BatchCompressionStats>,
+        bytes_written: &AtomicUsize,
+    ) -> Result<(), SwapVecError> {
+        let mut compression_buffers = Vec::new();
+        for job in receiver {
+            match job {
+                Job::Batches(batches) => {
+                    write_compressed(
+                        writer,
+                        compression,
+                        &batches,
+                        &mut compression_buffers,
+                        stats,
+                    )?;
+                    bytes_written.store(writer.bytes_written(), Ordering::Relaxed);
+                }
+                Job::Sync(reply) => {
+                    // The receiver might have given up waiting
+                    let _ = reply.send((writer.batch_infos().to_vec(), writer.checksum()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Queue serialized batches. Blocks if the queue is full.
+    /// Returns `false` if the thread has stopped after an error,
+    /// which is returned by [`WriterThread::join`].
+    pub fn send(&mut self, batches: Vec<SerializedBatch>) -> bool {
+        let batch_count = batches.len();
+        let element_count: usize = batches.iter().map(|(_, elements)| elements).sum();
+        let sent = self
+            .sender
+            .as_ref()
+            .unwrap()
+            .send(Job::Batches(batches))
+            .is_ok();
+        if sent {
+            self.batch_count += batch_count;
+            self.element_count += element_count;
+        }
+        sent
+    }
+
+    /// Wait until all queued batches are written,
+    /// and return the information needed to read them.
+    pub fn sync(&self) -> Result<(Vec<BatchInfo>, Checksum), SwapVecError> {
+        let (reply, receiver) = mpsc::channel();
+        let stopped = || io::Error::other("background writer stopped after an error");
+        self.sender
+            .as_ref()
+            .unwrap()
+            .send(Job::Sync(reply))
+            .map_err(|_| stopped())?;
+        Ok(receiver.recv().map_err(|_| stopped())?)
+    }
+
+    /// True if the thread has stopped after an error.
+    pub fn has_failed(&self) -> bool {
+        self.handle.as_ref().is_some_and(|h| h.is_finished())
+    }
+
+    /// Number of batches written or queued.
+    pub fn batch_count(&self) -> usize {
+        self.batch_count
+    }
+
+    /// Number of elements written or queued.
+    pub fn element_count(&self) -> usize {
+        self.element_count
+    }
+
+    /// Size of the file, without the batches still queued.
+    pub fn bytes_written(&self) -> usize {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Wait for all queued batches to be written
+    /// and take back the writer.
+    pub fn join(mut self) -> WriterOutcome {
+        drop(self.sender.take());
+        match self.handle.take().unwrap().join() {
+            Ok(outcome) => outcome,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for WriterThread {
+    fn drop(&mut self) {
+        // Finish writing before the file is removed
+        drop(self.sender.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::VecDeque;
+
+/// Least recently used decoded batches, by batch index.
+pub(crate) struct BatchCache<T> {
+    capacity: usize,
+    // Most recently used first
+    batches: VecDeque<(usize, Vec<T>)>,
+}
+
+impl<T> BatchCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            batches: VecDeque::new(),
+        }
+    }
+
+    pub fn get(&mut self, batch: usize) -> Option<&[T]> {
+        let position = self.batches.iter().position(|(b, _)| *b == batch)?;
+        if position > 0 {
+            let entry = self.batches.remove(position).unwrap();
+            self.batches.push_front(entry);
+        }
+        Some(&self.batches[0].1)
+    }
+
+    pub fn insert(&mut self, batch: usize, elements: Vec<T>) {
+        self.batches.retain(|(b, _)| *b != batch);
+        self.batches.push_front((batch, elements));
+        self.batches.truncate(self.capacity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = BatchCache::new(2);
+        cache.insert(0, vec![0]);
+        cache.insert(1, vec![1]);
+        assert_eq!(cache.get(0), Some(&[0][..]));
+        cache.insert(2, vec![2]);
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.get(0), Some(&[0][..]));
+        assert_eq!(cache.get(2), Some(&[2][..]));
+    }
+}
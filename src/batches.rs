@@ -0,0 +1,240 @@
+use std::{collections::VecDeque, io::Write, thread};
+
+use crate::{
+    checkedfile::BatchWriter,
+    compression::{self, BatchCompressionStats, Compress},
+    error::SwapVecError,
+    swapvec::{Compression, SwapVecConfig},
+};
+
+pub(crate) type SizeEstimator<T> = Box<dyn Fn(&T) -> usize + Send + Sync>;
+
+/// Serialized batch and its element count.
+pub(crate) type SerializedBatch = (Vec<u8>, usize);
+
+/// Elements kept in memory until they are written in batches,
+/// deciding when the next batches are due and which elements
+/// they contain. Shared by all vectors writing the file format.
+pub(crate) struct PendingElements<T> {
+    elements: VecDeque<T>,
+    // Estimated bytes of every element and their sum, only
+    // tracked if the configuration has byte limits. Kept,
+    // so a changing estimate cannot unbalance the sum.
+    sizes: VecDeque<usize>,
+    bytes: usize,
+    size_estimator: Option<SizeEstimator<T>>,
+}
+
+impl<T> Default for PendingElements<T> {
+    fn default() -> Self {
+        Self {
+            elements: VecDeque::new(),
+            sizes: VecDeque::new(),
+            bytes: 0,
+            size_estimator: None,
+        }
+    }
+}
+
+impl<T> PendingElements<T> {
+    /// Add an element. Its size is only estimated if the
+    /// configuration has byte limits, with `default_size`
+    /// unless a size estimator has been set.
+    pub fn push(&mut self, config: &SwapVecConfig, element: T, default_size: impl Fn(&T) -> usize) {
+        if config.measures_bytes() {
+            let size = match self.size_estimator.as_ref() {
+                Some(estimator) => estimator(&element),
+                None => default_size(&element),
+            };
+            self.sizes.push_back(size);
+            self.bytes += size;
+        }
+        self.elements.push_back(element);
+    }
+
+    /// Set how the size of an element is estimated,
+    /// estimating the sizes of all elements again.
+    pub fn set_size_estimator(&mut self, config: &SwapVecConfig, estimator: SizeEstimator<T>) {
+        if config.measures_bytes() {
+            self.sizes = self.elements.iter().map(&estimator).collect();
+            self.bytes = self.sizes.iter().sum();
+        }
+        self.size_estimator = Some(estimator);
+    }
+
+    /// Take the size estimator, e.g. to estimate
+    /// the sizes of the elements when sorting them.
+    pub fn take_size_estimator(&mut self) -> Option<SizeEstimator<T>> {
+        self.size_estimator.take()
+    }
+
+    pub fn elements(&self) -> &VecDeque<T> {
+        &self.elements
+    }
+
+    pub fn into_elements(self) -> VecDeque<T> {
+        self.elements
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Elements in memory take more than `swap_after_bytes`.
+    pub fn over_byte_budget(&self, config: &SwapVecConfig) -> bool {
+        config
+            .swap_after_bytes
+            .is_some_and(|limit| self.bytes > limit)
+    }
+
+    pub fn batch_ready(&self, config: &SwapVecConfig, written_to_file: bool) -> bool {
+        // Exceeding the memory budget always writes,
+        // even if no batch is full yet.
+        if self.over_byte_budget(config) {
+            return true;
+        }
+        // Before creating the file, enough batches
+        // to train a dictionary might be needed.
+        // Batches compressed in parallel are written together.
+        let batches = if written_to_file {
+            1
+        } else {
+            config.dictionary_samples()
+        }
+        .max(config.compression_group());
+        let batch_full = self.elements.len() > config.batch_size.saturating_mul(batches)
+            || config
+                .batch_bytes
+                .is_some_and(|limit| self.bytes > limit.saturating_mul(batches));
+        batch_full && (written_to_file || self.elements.len() > config.swap_after)
+    }
+
+    fn take_batch(&mut self, config: &SwapVecConfig) -> Vec<T> {
+        if !config.measures_bytes() {
+            let count = config.batch_size.min(self.elements.len());
+            return self.elements.drain(0..count).collect();
+        }
+        let byte_limit = config.batch_bytes.unwrap_or(usize::MAX);
+        let mut count = 0;
+        let mut bytes = 0;
+        for size in self.sizes.iter() {
+            if count == config.batch_size || (count > 0 && bytes >= byte_limit) {
+                break;
+            }
+            bytes += size;
+            count += 1;
+        }
+        self.sizes.drain(0..count);
+        self.bytes -= bytes;
+        self.elements.drain(0..count).collect()
+    }
+
+    /// Take and serialize the next batches to write: before the
+    /// file is created the ones to train a dictionary on, which
+    /// is then set in `config`, otherwise the ones compressed together.
+    pub fn take_batches(
+        &mut self,
+        config: &mut SwapVecConfig,
+        written_to_file: bool,
+        mut serialize: impl FnMut(Vec<T>) -> Result<Vec<u8>, SwapVecError>,
+    ) -> Result<Vec<SerializedBatch>, SwapVecError> {
+        let count = if written_to_file || config.dictionary_samples() == 1 {
+            config.compression_group()
+        } else {
+            config.dictionary_samples()
+        };
+        let mut batches = Vec::new();
+        while batches.len() < count && !self.elements.is_empty() {
+            let batch = self.take_batch(config);
+            let elements = batch.len();
+            batches.push((serialize(batch)?, elements));
+        }
+        if !written_to_file {
+            // Stored in the file header
+            compression::train_dictionary(&mut config.compression, &batches);
+        }
+        Ok(batches)
+    }
+}
+
+/// Vectors writing the elements pushed to them in batches.
+pub(crate) trait WriteBatches<T> {
+    fn pending(&self) -> &PendingElements<T>;
+
+    fn config(&self) -> &SwapVecConfig;
+
+    fn has_file(&self) -> bool;
+
+    /// Take the next batches and write them,
+    /// creating the file before the first ones.
+    fn write_batch(&mut self) -> Result<(), SwapVecError>;
+
+    /// Write the batches due after a push. Only the next
+    /// batches are written, unless more are needed
+    /// to get back within the memory budget.
+    fn write_due_batches(&mut self) -> Result<(), SwapVecError> {
+        if !self.pending().batch_ready(self.config(), self.has_file()) {
+            return Ok(());
+        }
+        self.write_batch()?;
+        // Large elements might need more than one batch
+        // to get back within the memory budget.
+        while self.pending().over_byte_budget(self.config()) {
+            self.write_batch()?;
+        }
+        Ok(())
+    }
+}
+
+/// Compress serialized batches, if configured, and write them in order.
+/// Several batches are compressed in parallel, one thread each.
+/// `compression_buffers` are reused across calls.
+pub(crate) fn write_compressed<W: Write>(
+    writer: &mut BatchWriter<W>,
+    compression: &Option<Compression>,
+    batches: &[SerializedBatch],
+    compression_buffers: &mut Vec<Vec<u8>>,
+    stats: &mut Vec<BatchCompressionStats>,
+) -> Result<(), SwapVecError> {
+    if compression_buffers.len() < batches.len() {
+        compression_buffers.resize_with(batches.len(), Vec::new);
+    }
+    let compress = |(buffer, _): &SerializedBatch, output: &mut Vec<u8>| {
+        output.clear();
+        compression
+            .compress(buffer, output)
+            .map_err(SwapVecError::Compression)
+    };
+    let mut jobs = batches.iter().zip(compression_buffers.iter_mut());
+    if compression.is_some() && batches.len() == 1 {
+        jobs.try_for_each(|(batch, output)| compress(batch, output))?;
+    } else if compression.is_some() {
+        thread::scope(|scope| {
+            let threads: Vec<_> = jobs
+                .map(|(batch, output)| scope.spawn(move || compress(batch, output)))
+                .collect();
+            threads
+                .into_iter()
+                .try_for_each(|t| t.join().unwrap_or_else(|p| std::panic::resume_unwind(p)))
+        })?;
+    }
+    for ((buffer, elements), output) in batches.iter().zip(compression_buffers.iter()) {
+        let payload = if compression.is_none() {
+            buffer
+        } else {
+            output
+        };
+        writer.write_batch(payload, *elements)?;
+        stats.push(BatchCompressionStats {
+            elements: *elements,
+            uncompressed_bytes: buffer.len(),
+            stored_bytes: payload.len(),
+            compressed: compression::is_compressed(compression, payload),
+        });
+    }
+    Ok(())
+}
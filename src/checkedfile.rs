@@ -1,67 +1,394 @@
 use std::{
-    hash::{Hash, Hasher},
-    io::{self, BufReader, BufWriter, Error, Read, Seek, Write}, collections::hash_map::DefaultHasher,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Error, Read, Seek, Write},
+    path::{Path, PathBuf},
 };
 
-use crate::SwapVecError;
+use tempfile::TempPath;
+use twox_hash::XxHash64;
 
+use crate::{
+    fileformat::{self, BatchIndex, FileHeader, FrameHeader},
+    SwapVecError,
+};
+
+/// Checksum algorithm used to verify batches
+/// read back from the temporary file.
+///
+/// A batch with a mismatching checksum is reported as
+/// [`SwapVecError::WrongChecksum`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Checksum {
+    /// Do not verify batches. Saves a little CPU time,
+    /// but corruption might only show up as a
+    /// decompression or deserialization error, if at all.
+    None,
+    /// xxHash64 with seed 0. Fast and stable across
+    /// platforms and Rust versions.
+    #[default]
+    XxHash64,
+    /// CRC32C (Castagnoli), hardware accelerated on
+    /// most modern CPUs.
+    Crc32c,
+}
+
+impl Checksum {
+    pub(crate) fn id(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::XxHash64 => 1,
+            Self::Crc32c => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(Self::None),
+            1 => Some(Self::XxHash64),
+            2 => Some(Self::Crc32c),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn of(&self, bytes: &[u8]) -> u64 {
+        match self {
+            Self::None => 0,
+            Self::XxHash64 => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(bytes);
+                hasher.finish()
+            }
+            Self::Crc32c => crc32c::crc32c(bytes) as u64,
+        }
+    }
+}
+
+/// Handle to the file batches are written to, with its own position.
+///
+/// Reads and writes happen at this position, not at the cursor shared
+/// by cloned handles, so every reader can use its own clone of the
+/// handle, even if the file has no name to open it again.
 #[derive(Debug)]
+pub(crate) struct FileHandle {
+    file: File,
+    position: u64,
+}
+
+impl FileHandle {
+    pub fn new(file: File) -> Self {
+        Self { file, position: 0 }
+    }
+
+    /// Another handle to the same file, positioned at its start.
+    pub fn try_clone(&self) -> Result<Self, Error> {
+        Ok(Self::new(self.file.try_clone()?))
+    }
+
+    pub fn set_len(&self, len: u64) -> Result<(), Error> {
+        self.file.set_len(len)
+    }
+
+    /// The file, for IO using its own cursor.
+    #[cfg(feature = "async")]
+    pub fn into_file(self) -> File {
+        self.file
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> Result<usize, Error> {
+    std::os::unix::fs::FileExt::write_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> Result<usize, Error> {
+    std::os::windows::fs::FileExt::seek_write(file, buf, offset)
+}
+
+// Without positional IO, cloned handles must not be used concurrently
+#[cfg(not(any(unix, windows)))]
+fn read_at(mut file: &File, buf: &mut [u8], offset: u64) -> Result<usize, Error> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn write_at(mut file: &File, buf: &[u8], offset: u64) -> Result<usize, Error> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.write(buf)
+}
+
+impl Read for FileHandle {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = read_at(&self.file, buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for FileHandle {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let written = write_at(&self.file, buf, self.position)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.file.flush()
+    }
+}
+
+impl Seek for FileHandle {
+    fn seek(&mut self, pos: io::SeekFrom) -> Result<u64, Error> {
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            io::SeekFrom::End(offset) => self.file.metadata()?.len().checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Location of the file batches are written to.
+#[derive(Debug)]
+pub(crate) enum SwapFilePath {
+    /// Removed right after creation, so it is gone as soon as
+    /// the process ends, even if killed. Only reachable through
+    /// the kept handle.
+    Unlinked(FileHandle),
+    /// Removes the file when dropped
+    Temporary(TempPath),
+    /// Created by `SwapVec::persist`, never removed
+    Persistent(PathBuf),
+}
+
+impl SwapFilePath {
+    /// Path of the file, if it still has one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Unlinked(_) => None,
+            Self::Temporary(path) => Some(path),
+            Self::Persistent(path) => Some(path),
+        }
+    }
+
+    /// Open another handle to read the file with.
+    pub fn open(&self) -> Result<FileHandle, Error> {
+        match self {
+            Self::Unlinked(handle) => handle.try_clone(),
+            Self::Temporary(path) => Ok(FileHandle::new(File::open(path)?)),
+            Self::Persistent(path) => Ok(FileHandle::new(File::open(path)?)),
+        }
+    }
+
+    /// Move the file to `new_path`, which will not be removed anymore.
+    /// Falls back to copying, if the file can not be renamed,
+    /// e.g. because it is moved to another file system
+    /// or has no name anymore.
+    pub fn persist(self, new_path: &Path) -> Result<(), Error> {
+        match self {
+            Self::Unlinked(handle) => {
+                io::copy(&mut handle.try_clone()?, &mut File::create(new_path)?)?;
+            }
+            Self::Temporary(path) => {
+                if let Err(e) = path.persist(new_path) {
+                    fs::copy(&e.path, new_path)?;
+                }
+            }
+            Self::Persistent(path) => {
+                if path != new_path && fs::rename(&path, new_path).is_err() {
+                    fs::copy(&path, new_path)?;
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BatchInfo {
+    // Position of the frame header in the file
+    pub offset: u64,
     pub hash: u64,
     pub bytes: usize,
+    pub elements: usize,
 }
 
 pub(crate) struct BatchWriter<T: Write> {
     inner: BufWriter<T>,
     batch_infos: Vec<BatchInfo>,
+    checksum: Checksum,
+    position: u64,
+    element_count: usize,
 }
 
 pub(crate) struct BatchReader<T: Read> {
     inner: BufReader<T>,
     batch_infos: Vec<BatchInfo>,
+    // Index of the first element of every batch,
+    // followed by the total element count.
+    batch_starts: Vec<usize>,
     batch_index: usize,
+    // Seek to the frame of batch_index before reading
+    seek_pending: bool,
     buffer: Vec<u8>,
-}
-
-fn hash_bytes(bytes: &[u8]) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    bytes.hash(&mut hasher);
-    hasher.finish()
+    checksum: Checksum,
 }
 
 impl<T: Write> BatchWriter<T> {
-    pub fn new(writer: T) -> Self {
-        Self {
+    pub fn new(writer: T, header: FileHeader) -> Result<Self, io::Error> {
+        let mut inner = BufWriter::new(writer);
+        header.write_to(&mut inner)?;
+        Ok(Self {
             batch_infos: Vec::new(),
+            inner,
+            checksum: header.checksum,
+            position: header.len(),
+            element_count: 0,
+        })
+    }
+    /// Continue writing a file after its last batch.
+    /// The writer must already be positioned there.
+    pub fn resume(
+        writer: T,
+        checksum: Checksum,
+        batch_infos: Vec<BatchInfo>,
+        position: u64,
+    ) -> Self {
+        Self {
             inner: BufWriter::new(writer),
+            element_count: batch_infos.iter().map(|b| b.elements).sum(),
+            batch_infos,
+            checksum,
+            position,
         }
     }
-    pub fn write_batch(&mut self, buffer: &[u8]) -> Result<(), io::Error> {
-        self.inner.write_all(buffer)?;
-        self.batch_infos.push(BatchInfo {
-            hash: hash_bytes(buffer),
+    pub fn write_batch(&mut self, buffer: &[u8], elements: usize) -> Result<(), io::Error> {
+        let info = BatchInfo {
+            offset: self.position,
+            hash: self.checksum.of(buffer),
             bytes: buffer.len(),
-        });
+            elements,
+        };
+        FrameHeader {
+            bytes: info.bytes as u64,
+            elements: info.elements as u64,
+            hash: info.hash,
+        }
+        .write_to(&mut self.inner)?;
+        self.inner.write_all(buffer)?;
+        self.position += fileformat::FRAME_HEADER_LEN + buffer.len() as u64;
+        self.element_count += elements;
+        self.batch_infos.push(info);
         self.inner.flush()
     }
+    /// Write the batch index, which ends the file.
+    pub fn finish(mut self) -> Result<(T, Vec<BatchInfo>, Checksum), io::Error> {
+        fileformat::write_index(&mut self.inner, &self.batch_infos, self.position)?;
+        let inner = self
+            .inner
+            .into_inner()
+            .map_err(|inner_error| inner_error.into_error())?;
+        Ok((inner, self.batch_infos, self.checksum))
+    }
     pub fn bytes_written(&self) -> usize {
-        self.batch_infos.iter().map(|b| b.bytes).sum()
+        self.position as usize
     }
     pub fn batch_count(&self) -> usize {
         self.batch_infos.len()
     }
+    pub fn element_count(&self) -> usize {
+        self.element_count
+    }
+    pub fn batch_infos(&self) -> &[BatchInfo] {
+        &self.batch_infos
+    }
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
+    }
+    /// Bytes written to `T` so far, e.g. to take
+    /// the bytes out of a `Vec<u8>` after a batch.
+    #[cfg(feature = "async")]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+/// Check a frame, its header followed by the payload,
+/// against the batch info, and return the payload.
+pub(crate) fn check_frame<'a>(
+    frame: &'a [u8],
+    batch_info: &BatchInfo,
+    checksum: Checksum,
+) -> Result<&'a [u8], SwapVecError> {
+    let (mut header, payload) = frame.split_at(fileformat::FRAME_HEADER_LEN as usize);
+    let header = FrameHeader::read_from(&mut header)?;
+    if header.bytes != batch_info.bytes as u64 || header.hash != batch_info.hash {
+        return Err(SwapVecError::InvalidFormat);
+    }
+    if checksum != Checksum::None && checksum.of(payload) != batch_info.hash {
+        return Err(SwapVecError::WrongChecksum);
+    }
+    Ok(payload)
 }
 
 impl<T: Read + Seek> BatchReader<T> {
+    /// Read the given batches of a file, e.g.
+    /// from another handle to a file still being written.
+    pub fn new(inner: T, batch_infos: Vec<BatchInfo>, checksum: Checksum) -> Result<Self, Error> {
+        let mut reader = Self {
+            inner: BufReader::new(inner),
+            batch_infos: Vec::new(),
+            batch_starts: vec![0],
+            batch_index: 0,
+            seek_pending: true,
+            buffer: Vec::new(),
+            checksum,
+        };
+        reader.extend_batch_infos(&batch_infos);
+        Ok(reader)
+    }
+
+    /// Read an existing file, written by a `BatchWriter`.
+    pub fn open(mut inner: T) -> Result<(FileHeader, BatchIndex, Self), SwapVecError> {
+        inner.seek(io::SeekFrom::Start(0))?;
+        let header = FileHeader::read_from(&mut inner)?;
+        let index = fileformat::read_batch_index(&mut inner, header.len(), header.checksum)?;
+        let reader = Self::new(inner, index.batch_infos.clone(), header.checksum)?;
+        Ok((header, index, reader))
+    }
+
+    /// Continue reading at the given batch.
+    pub fn seek_batch(&mut self, batch: usize) -> Result<(), Error> {
+        self.batch_index = batch;
+        self.seek_pending = true;
+        Ok(())
+    }
+
     pub fn reset(&mut self) -> Result<(), Error> {
-        self.inner.seek(io::SeekFrom::Start(0))?;
-        self.batch_index = 0;
         self.buffer.clear();
-        Ok(())
+        self.seek_batch(0)
     }
-}
 
-impl<T: Read> BatchReader<T> {
     pub fn read_batch(&mut self) -> Result<Option<&[u8]>, SwapVecError> {
         let batch_info = self.batch_infos.get(self.batch_index);
         self.batch_index += 1;
@@ -69,12 +396,62 @@ impl<T: Read> BatchReader<T> {
             return Ok(None);
         }
         let batch_info = batch_info.unwrap();
-        self.buffer.resize(batch_info.bytes, 0);
+        if self.seek_pending {
+            self.inner.seek(io::SeekFrom::Start(batch_info.offset))?;
+            self.seek_pending = false;
+        }
+        self.buffer
+            .resize(fileformat::FRAME_HEADER_LEN as usize + batch_info.bytes, 0);
         self.inner.read_exact(self.buffer.as_mut_slice())?;
-        if hash_bytes(self.buffer.as_slice()) != batch_info.hash {
-            // return Err(SwapVecError::WrongChecksum);
+        check_frame(&self.buffer, batch_info, self.checksum).map(Some)
+    }
+}
+
+impl<T: Read> BatchReader<T> {
+    /// Make batches written after creating the reader readable.
+    pub fn extend_batch_infos(&mut self, batch_infos: &[BatchInfo]) {
+        for info in batch_infos {
+            let start = self.batch_starts[self.batch_starts.len() - 1];
+            self.batch_starts.push(start + info.elements);
+            self.batch_infos.push(info.clone());
+        }
+    }
+
+    pub fn batch_count(&self) -> usize {
+        self.batch_infos.len()
+    }
+
+    pub fn batch_infos(&self) -> &[BatchInfo] {
+        &self.batch_infos
+    }
+
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Index of the batch `read_batch` returns next.
+    pub fn batch_index(&self) -> usize {
+        self.batch_index
+    }
+
+    pub fn element_count(&self) -> usize {
+        self.batch_starts[self.batch_starts.len() - 1]
+    }
+
+    /// Index of the first element of the given batch.
+    /// Passing `batch_count()` returns the total element count.
+    pub fn batch_start(&self, batch: usize) -> usize {
+        self.batch_starts[batch]
+    }
+
+    /// Find the batch containing the element with the given index,
+    /// and the position of the element in this batch.
+    pub fn locate(&self, element: usize) -> Option<(usize, usize)> {
+        if element >= self.element_count() {
+            return None;
         }
-        Ok(Some(self.buffer.as_slice()))
+        let batch = self.batch_starts.partition_point(|start| *start <= element) - 1;
+        Some((batch, element - self.batch_starts[batch]))
     }
 }
 
@@ -82,17 +459,8 @@ impl<T: Read + Write + Seek> TryFrom<BatchWriter<T>> for BatchReader<T> {
     type Error = std::io::Error;
 
     fn try_from(value: BatchWriter<T>) -> Result<Self, Self::Error> {
-        let mut inner = value
-            .inner
-            .into_inner()
-            .map_err(|inner_error| inner_error.into_error())?;
-        inner.seek(io::SeekFrom::Start(0))?;
-        Ok(Self {
-            inner: BufReader::new(inner),
-            batch_infos: value.batch_infos,
-            batch_index: 0,
-            buffer: Vec::new(),
-        })
+        let (inner, batch_infos, checksum) = value.finish()?;
+        Self::new(inner, batch_infos, checksum)
     }
 }
 
@@ -102,19 +470,30 @@ mod test {
 
     use super::*;
 
+    fn header(checksum: Checksum) -> FileHeader {
+        FileHeader {
+            version: fileformat::VERSION,
+            compression: 0,
+            checksum,
+            serialization: 0,
+            type_tag: 0,
+            dictionary: None,
+            batch_tags: false,
+        }
+    }
+
     #[test]
     fn read_write_checked_io() {
         let buffer = Cursor::new(vec![0; 128]);
-        let mut batch_writer = BatchWriter::new(buffer);
+        let mut batch_writer = BatchWriter::new(buffer, header(Checksum::XxHash64))
+            .expect("Could not write to IO buffer");
         batch_writer
-            .write_batch(&[1, 2, 3])
+            .write_batch(&[1, 2, 3], 3)
             .expect("Could not write to IO buffer");
         batch_writer
-            .write_batch(&[44, 55])
+            .write_batch(&[44, 55], 2)
             .expect("Could not write to IO buffer");
 
-        // batch_writer.wtf();
-        // panic!()
         let mut reader: BatchReader<_> = batch_writer
             .try_into()
             .expect("Could not flush into IO buffer");
@@ -126,6 +505,8 @@ mod test {
             &[1, 2, 3]
         );
         reader.reset().expect("Could not reset");
+        assert_eq!(reader.locate(3), Some((1, 0)));
+        assert_eq!(reader.locate(5), None);
         assert_eq!(
             reader
                 .read_batch()
@@ -140,5 +521,56 @@ mod test {
                 .expect("Batch was unexpectedly empty"),
             &[44, 55]
         );
+        reader.seek_batch(0).expect("Could not seek");
+        assert_eq!(
+            reader
+                .read_batch()
+                .expect("Could not read batch")
+                .expect("Batch was unexpectedly empty"),
+            &[1, 2, 3]
+        );
+    }
+
+    fn corrupted_reader(checksum: Checksum) -> BatchReader<Cursor<Vec<u8>>> {
+        let mut batch_writer = BatchWriter::new(Cursor::new(Vec::new()), header(checksum))
+            .expect("Could not write to IO buffer");
+        batch_writer
+            .write_batch(&[1, 2, 3], 3)
+            .expect("Could not write to IO buffer");
+        let payload_start = (fileformat::HEADER_LEN + fileformat::FRAME_HEADER_LEN) as usize;
+        batch_writer.inner.get_mut().get_mut()[payload_start + 1] = 99;
+        batch_writer
+            .try_into()
+            .expect("Could not flush into IO buffer")
+    }
+
+    #[test]
+    fn detect_wrong_checksum() {
+        for checksum in [Checksum::XxHash64, Checksum::Crc32c] {
+            let mut reader = corrupted_reader(checksum);
+            assert!(matches!(
+                reader.read_batch(),
+                Err(SwapVecError::WrongChecksum)
+            ));
+        }
+    }
+
+    #[test]
+    fn skip_checksum() {
+        let mut reader = corrupted_reader(Checksum::None);
+        assert_eq!(
+            reader
+                .read_batch()
+                .expect("Could not read batch")
+                .expect("Batch was unexpectedly empty"),
+            &[1, 99, 3]
+        );
+    }
+
+    #[test]
+    fn stable_checksums() {
+        assert_eq!(Checksum::XxHash64.of(b""), 0xef46_db37_51d8_e999);
+        assert_eq!(Checksum::XxHash64.of(b"abc"), 0x44bc_2cf5_ad77_0999);
+        assert_eq!(Checksum::Crc32c.of(b"123456789"), 0xe306_9283);
     }
 }
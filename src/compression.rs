@@ -1,6 +1,40 @@
-use lz4_flex::{compress_prepend_size, decompress_size_prepended};
+#[cfg(any(feature = "deflate", feature = "zstd"))]
+use crate::swapvec::CompressionLevel;
+#[cfg(feature = "zstd")]
+use crate::swapvec::ZstdDictionary;
+use crate::Compression;
 
-use crate::{swapvec::CompressionLevel, Compression};
+/// Error of a compression algorithm, carried in
+/// [`SwapVecError::Compression`](crate::SwapVecError::Compression)
+/// and [`SwapVecError::Decompression`](crate::SwapVecError::Decompression).
+#[derive(Debug)]
+pub struct CompressionError(Box<dyn std::error::Error + Send + Sync>);
+
+impl CompressionError {
+    /// Wrap the error of your compression algorithm,
+    /// or a message describing it.
+    pub fn new(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self(error.into())
+    }
+}
+
+impl std::fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for CompressionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+impl From<std::io::Error> for CompressionError {
+    fn from(value: std::io::Error) -> Self {
+        Self::new(value)
+    }
+}
 
 /// Provide your own compression algorithm by
 /// creating an empty struct implementing `compress`
@@ -10,82 +44,493 @@ use crate::{swapvec::CompressionLevel, Compression};
 /// but _must_ always decompress into the same
 /// bytes. Undefined behaviour otherwise.
 ///
-/// Note: You must always also implement
-/// CompressBoxedClone, to allow cloning
-/// and debugging of the configuration.
+/// Pass it to the configuration with
+/// [`Compression::Custom`](crate::Compression::Custom).
 ///
 /// ```rust
-/// use swapvec::Compress;
+/// use swapvec::{Compress, CompressionError};
+/// #[derive(Debug)]
 /// struct DummyCompression;
 /// impl Compress for DummyCompression {
-///   fn compress(&self, block: Vec<u8>) -> Vec<u8> {
-///       block
+///   fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+///       output.extend_from_slice(block);
+///       Ok(())
+///   }
+///   fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+///       output.extend_from_slice(block);
+///       Ok(())
 ///   }
-///   fn decompress(&self, block: Vec<u8>) -> Result<Vec<u8>, ()> {
-///       Ok(block)
+///   fn codec_id(&self) -> u8 {
+///       200
 ///   }
 /// }
 ///
 /// let bytes = vec![1, 2, 3];
 /// let compression = DummyCompression;
-/// assert_eq!(bytes, compression.decompress(compression.compress(bytes.clone())).unwrap());
+/// let mut compressed = Vec::new();
+/// compression.compress(&bytes, &mut compressed).unwrap();
+/// let mut decompressed = Vec::new();
+/// compression.decompress(&compressed, &mut decompressed).unwrap();
+/// assert_eq!(bytes, decompressed);
 /// ```
-pub trait Compress {
-    /// Compress bytes blockwise. The compressed block
+pub trait Compress: std::fmt::Debug {
+    /// Compress bytes blockwise, appending to `output`,
+    /// which is empty, but might have capacity left from
+    /// an earlier batch. The compressed block
     /// will be put into `self.decompress()` later.
-    fn compress(&self, block: Vec<u8>) -> Vec<u8>;
-    /// Receive block which was earlier `compress()`ed.
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError>;
+    /// Receive block which was earlier `compress()`ed,
+    /// and append the decompressed bytes to the empty `output`.
     /// If the result is `Ok`, the same bytes which were
     /// `compress()`es earlier are expected.
-    fn decompress(&self, block: Vec<u8>) -> Result<Vec<u8>, ()>;
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError>;
+    /// Id of the algorithm, stored in the file header.
+    /// A file can only be read with the same id it has been written with.
+    ///
+    /// Ids below 128 are reserved for the built-in algorithms.
+    fn codec_id(&self) -> u8 {
+        u8::MAX
+    }
 }
 
-impl Compress for Option<Compression> {
-    fn compress(&self, block: Vec<u8>) -> Vec<u8> {
+#[cfg(feature = "lz4")]
+fn lz4_compress(block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+    // Same layout as lz4_flex::compress_prepend_size
+    output.extend_from_slice(&(block.len() as u32).to_le_bytes());
+    let start = output.len();
+    output.resize(
+        start + lz4_flex::block::get_maximum_output_size(block.len()),
+        0,
+    );
+    let written =
+        lz4_flex::compress_into(block, &mut output[start..]).map_err(CompressionError::new)?;
+    output.truncate(start + written);
+    Ok(())
+}
+
+#[cfg(feature = "lz4")]
+fn lz4_decompress(block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+    if block.len() < 4 {
+        return Err(CompressionError::new("LZ4 block without size"));
+    }
+    let size = u32::from_le_bytes([block[0], block[1], block[2], block[3]]) as usize;
+    let start = output.len();
+    output.resize(start + size, 0);
+    let written = lz4_flex::decompress_into(&block[4..], &mut output[start..])
+        .map_err(CompressionError::new)?;
+    if written != size {
+        return Err(CompressionError::new("LZ4 block shorter than its size"));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_level(level: &CompressionLevel) -> i32 {
+    match level {
+        CompressionLevel::Fast => 1,
+        CompressionLevel::Default => zstd::DEFAULT_COMPRESSION_LEVEL,
+        CompressionLevel::Slow => 19,
+        CompressionLevel::Numeric(n) => *n,
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_dictionary(dictionary: &ZstdDictionary) -> &[u8] {
+    match dictionary {
+        ZstdDictionary::Given(dictionary) => dictionary,
+        // Not trained yet
+        ZstdDictionary::None | ZstdDictionary::Train { .. } => &[],
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_compress(
+    block: &[u8],
+    output: &mut Vec<u8>,
+    level: i32,
+    dictionary: &[u8],
+) -> Result<(), CompressionError> {
+    use std::io::Write;
+    let mut encoder = zstd::stream::write::Encoder::with_dictionary(output, level, dictionary)?;
+    encoder.write_all(block)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+#[cfg(feature = "deflate")]
+fn deflate_compress(block: &[u8], output: &mut Vec<u8>, level: u8) -> Result<(), CompressionError> {
+    use miniz_oxide::deflate::core::{
+        compress, create_comp_flags_from_zip_params, CompressorOxide, TDEFLFlush, TDEFLStatus,
+    };
+    // Raw deflate stream, as written by compress_to_vec
+    let flags = create_comp_flags_from_zip_params(level.into(), 0, 0);
+    let mut compressor = Box::new(CompressorOxide::new(flags));
+    let start = output.len();
+    output.resize(start + (block.len() / 2).max(64), 0);
+    let (mut read, mut written) = (0, start);
+    loop {
+        let (status, bytes_in, bytes_out) = compress(
+            &mut compressor,
+            &block[read..],
+            &mut output[written..],
+            TDEFLFlush::Finish,
+        );
+        read += bytes_in;
+        written += bytes_out;
+        match status {
+            TDEFLStatus::Done => break,
+            TDEFLStatus::Okay => output.resize(output.len() * 2, 0),
+            status => return Err(CompressionError::new(format!("Deflate: {status:?}"))),
+        }
+    }
+    output.truncate(written);
+    Ok(())
+}
+
+#[cfg(feature = "deflate")]
+fn deflate_decompress(block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+    use miniz_oxide::inflate::core::{
+        decompress, inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF, DecompressorOxide,
+    };
+    use miniz_oxide::inflate::TINFLStatus;
+    let mut decompressor = Box::<DecompressorOxide>::default();
+    let start = output.len();
+    output.resize(start + block.len().saturating_mul(2).max(64), 0);
+    let (mut read, mut written) = (0, 0);
+    loop {
+        // Matches may refer to everything decompressed so far,
+        // but not to the bytes in front of `start`.
+        let (status, bytes_in, bytes_out) = decompress(
+            &mut decompressor,
+            &block[read..],
+            &mut output[start..],
+            written,
+            TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+        );
+        read += bytes_in;
+        written += bytes_out;
+        match status {
+            TINFLStatus::Done => break,
+            TINFLStatus::HasMoreOutput => output.resize(output.len() * 2, 0),
+            status => return Err(CompressionError::new(format!("Deflate: {status:?}"))),
+        }
+    }
+    output.truncate(start + written);
+    Ok(())
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decompress(
+    block: &[u8],
+    output: &mut Vec<u8>,
+    dictionary: &[u8],
+) -> Result<(), CompressionError> {
+    use std::io::Read;
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(block, dictionary)?;
+    decoder.read_to_end(output)?;
+    Ok(())
+}
+
+/// Tags in front of every batch with [`Compression::Adaptive`].
+const BATCH_RAW: u8 = 0;
+const BATCH_COMPRESSED: u8 = 1;
+
+impl Compress for Compression {
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
         match self {
-            Some(Compression::Lz4) => compress_prepend_size(&block).to_vec(),
-            Some(Compression::Deflate(level)) => {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_compress(block, output),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate(level) => {
                 let compression_level = match level {
                     CompressionLevel::Fast => 2,
                     CompressionLevel::Default => 6,
                     CompressionLevel::Slow => 9,
+                    CompressionLevel::Numeric(n) => (*n).clamp(0, 10) as u8,
                 };
-                miniz_oxide::deflate::compress_to_vec(&block, compression_level)
+                deflate_compress(block, output, compression_level)
             }
-            Some(Compression::Custom(algo)) => algo.compress(block),
-            None => block,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level, dictionary } => zstd_compress(
+                block,
+                output,
+                zstd_level(level),
+                zstd_dictionary(dictionary),
+            ),
+            Compression::Adaptive {
+                compression,
+                max_ratio,
+            } => {
+                output.push(BATCH_COMPRESSED);
+                compression.compress(block, output)?;
+                if (output.len() - 1) as f64 > block.len() as f64 * max_ratio {
+                    output.clear();
+                    output.push(BATCH_RAW);
+                    output.extend_from_slice(block);
+                }
+                Ok(())
+            }
+            Compression::Custom(algo) => algo.compress(block, output),
         }
     }
-    fn decompress(&self, block: Vec<u8>) -> Result<Vec<u8>, ()> {
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
         match self {
-            Some(Compression::Lz4) => decompress_size_prepended(&block).map_err(|_| ()),
-            Some(Compression::Deflate(_)) => {
-                miniz_oxide::inflate::decompress_to_vec(&block).map_err(|_| ())
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_decompress(block, output),
+            #[cfg(feature = "deflate")]
+            Compression::Deflate(_) => deflate_decompress(block, output),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { dictionary, .. } => {
+                zstd_decompress(block, output, zstd_dictionary(dictionary))
             }
-            Some(Compression::Custom(algo)) => algo.decompress(block),
-            None => Ok(block),
+            Compression::Adaptive { compression, .. } => match block.split_first() {
+                Some((&BATCH_RAW, raw)) => {
+                    output.extend_from_slice(raw);
+                    Ok(())
+                }
+                Some((&BATCH_COMPRESSED, compressed)) => compression.decompress(compressed, output),
+                _ => Err(CompressionError::new("Unknown batch tag")),
+            },
+            Compression::Custom(algo) => algo.decompress(block, output),
+        }
+    }
+    fn codec_id(&self) -> u8 {
+        match self {
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => 1,
+            #[cfg(feature = "deflate")]
+            Compression::Deflate(_) => 2,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => 3,
+            // Whether batches are tagged is stored separately
+            Compression::Adaptive { compression, .. } => compression.codec_id(),
+            Compression::Custom(algo) => algo.codec_id(),
         }
     }
 }
 
-/// Your custom compression algorithm struct must be debugable
-/// and clonable. Implement this trait to keep the main
-/// configuration debugable and clonable.
+impl Compress for Option<Compression> {
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        match self {
+            Some(compression) => compression.compress(block, output),
+            None => {
+                output.extend_from_slice(block);
+                Ok(())
+            }
+        }
+    }
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        match self {
+            Some(compression) => compression.decompress(block, output),
+            None => {
+                output.extend_from_slice(block);
+                Ok(())
+            }
+        }
+    }
+    fn codec_id(&self) -> u8 {
+        self.as_ref()
+            .map_or(0, |compression| compression.codec_id())
+    }
+}
+
+/// True if every batch is tagged with whether it has been compressed.
+pub(crate) fn is_adaptive(compression: &Option<Compression>) -> bool {
+    matches!(compression, Some(Compression::Adaptive { .. }))
+}
+
+/// True if a batch written with the given compression has been compressed.
+pub(crate) fn is_compressed(compression: &Option<Compression>, payload: &[u8]) -> bool {
+    match compression {
+        None => false,
+        Some(Compression::Adaptive { .. }) => payload.first() == Some(&BATCH_COMPRESSED),
+        Some(_) => true,
+    }
+}
+
+/// The algorithm applied to the batches, without adaptive mode.
+fn codec_mut(compression: &mut Compression) -> &mut Compression {
+    match compression {
+        Compression::Adaptive { compression, .. } => codec_mut(compression),
+        other => other,
+    }
+}
+
+fn codec(compression: &Compression) -> &Compression {
+    match compression {
+        Compression::Adaptive { compression, .. } => codec(compression),
+        other => other,
+    }
+}
+
+/// Number of batches and maximum size of a dictionary to train.
+#[cfg(feature = "zstd")]
+pub(crate) fn dictionary_training(compression: &Option<Compression>) -> Option<(usize, usize)> {
+    match compression.as_ref().map(codec) {
+        Some(Compression::Zstd {
+            dictionary: ZstdDictionary::Train { batches, max_size },
+            ..
+        }) => Some((*batches, *max_size)),
+        _ => None,
+    }
+}
+
+/// Dictionary to store in the file header, if any.
+pub(crate) fn dictionary(compression: &Option<Compression>) -> Option<Vec<u8>> {
+    match compression.as_ref().map(codec) {
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd {
+            dictionary: ZstdDictionary::Given(dictionary),
+            ..
+        }) => Some(dictionary.clone()),
+        _ => None,
+    }
+}
+
+/// Use the dictionary stored in the header of an existing file,
+/// or a newly trained one.
+pub(crate) fn set_dictionary(compression: &mut Option<Compression>, stored: Option<Vec<u8>>) {
+    match compression.as_mut().map(codec_mut) {
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd { dictionary, .. }) => {
+            *dictionary = stored.map_or(ZstdDictionary::None, ZstdDictionary::Given);
+        }
+        _ => drop(stored),
+    }
+}
+
+/// Train a dictionary on the first batches, if configured,
+/// before creating the file. Falls back to no dictionary,
+/// if training fails, e.g. because there is too little data.
+pub(crate) fn train_dictionary(
+    compression: &mut Option<Compression>,
+    samples: &[(Vec<u8>, usize)],
+) {
+    #[cfg(feature = "zstd")]
+    if let Some((_, max_size)) = dictionary_training(compression) {
+        let buffers: Vec<&[u8]> = samples.iter().map(|(b, _)| b.as_slice()).collect();
+        let dictionary = zstd::dict::from_samples(&buffers, max_size).ok();
+        set_dictionary(compression, dictionary);
+    }
+    #[cfg(not(feature = "zstd"))]
+    let _ = (compression, samples);
+}
+
+/// Compression of a batch written to disk,
+/// see [`SwapVec::compression_stats`](crate::SwapVec::compression_stats).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchCompressionStats {
+    /// Number of elements in the batch
+    pub elements: usize,
+    /// Size of the serialized batch
+    pub uncompressed_bytes: usize,
+    /// Size of the batch on disk
+    pub stored_bytes: usize,
+    /// False if the batch has been stored uncompressed,
+    /// either without compression or in adaptive mode.
+    pub compressed: bool,
+}
+
+impl BatchCompressionStats {
+    /// Stored size divided by uncompressed size.
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_bytes == 0 {
+            return 1.0;
+        }
+        self.stored_bytes as f64 / self.uncompressed_bytes as f64
+    }
+}
+
+/// Clone a compression algorithm into a new Box.
+///
+/// Implemented for every `Compress` type which is `Clone`.
+///
+/// Not needed anymore, [`Compression::Custom`] shares the
+/// algorithm in an `Arc` instead of cloning it.
+#[deprecated(
+    note = "Compression::Custom takes an Arc<dyn Compress + Send + Sync>, no cloning needed"
+)]
 pub trait CompressBoxedClone: Compress + std::fmt::Debug {
-    /// Clone your empty struct and return it as a new Box.
+    /// Clone your struct and return it as a new Box.
     fn boxed_clone(&self) -> Box<dyn CompressBoxedClone>;
 }
 
-#[cfg(test)]
+#[allow(deprecated)]
+impl<T: Compress + Clone + std::fmt::Debug + 'static> CompressBoxedClone for T {
+    fn boxed_clone(&self) -> Box<dyn CompressBoxedClone> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(all(test, any(feature = "lz4", feature = "deflate", feature = "zstd")))]
 mod test {
     use super::*;
 
+    fn roundtrip(compression: &Option<Compression>, data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        compression.compress(data, &mut compressed).unwrap();
+        let mut decompressed = Vec::new();
+        compression
+            .decompress(&compressed, &mut decompressed)
+            .unwrap();
+        decompressed
+    }
+
+    #[cfg(feature = "lz4")]
     #[test]
     fn test_lz4() {
         let compression = Some(Compression::Lz4);
         let data: Vec<u8> = (0..u8::MAX).collect();
-        let compressed = compression.compress(data.clone());
-        let decompressed = compression.decompress(compressed).unwrap();
-        assert_eq!(decompressed, data);
+        assert_eq!(roundtrip(&compression, &data), data);
+
+        // Files written with lz4_flex::compress_prepend_size stay readable
+        let mut compressed = Vec::new();
+        compression.compress(&data, &mut compressed).unwrap();
+        assert_eq!(compressed, lz4_flex::compress_prepend_size(&data));
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_deflate() {
+        let compression = Some(Compression::Deflate(CompressionLevel::Numeric(20)));
+        let data: Vec<u8> = (0..u8::MAX).collect();
+        assert_eq!(roundtrip(&compression, &data), data);
+
+        // Larger than the initial buffers, in both directions
+        let data: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let compression = Some(Compression::Deflate(CompressionLevel::Default));
+        assert_eq!(roundtrip(&compression, &data), data);
+        let random: Vec<u8> = (0..10_000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        assert_eq!(roundtrip(&compression, &random), random);
+
+        // Files written with compress_to_vec stay readable
+        let mut compressed = vec![7];
+        compression.compress(&data, &mut compressed).unwrap();
+        assert_eq!(
+            compressed[1..],
+            miniz_oxide::deflate::compress_to_vec(&data, 6)
+        );
+        let mut decompressed = vec![7];
+        compression
+            .decompress(&compressed[1..], &mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed[1..], data);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_dictionary() {
+        let data: Vec<u8> = (0..u8::MAX).collect();
+        for dictionary in [ZstdDictionary::None, ZstdDictionary::Given(data.clone())] {
+            let compression = Some(Compression::Zstd {
+                level: CompressionLevel::Numeric(3),
+                dictionary,
+            });
+            assert_eq!(roundtrip(&compression, &data), data);
+        }
     }
 }
@@ -1,3 +1,6 @@
+use crate::compression::CompressionError;
+use crate::serialization::SerializationError;
+
 /// A collection of all possible errors.
 ///
 /// Errors could be divided into write and read
@@ -12,15 +15,26 @@ pub enum SwapVecError {
     OutOfDisk,
     /// A read back batch had a wrong checksum
     WrongChecksum,
+    /// The file is not a SwapVec file, written by an
+    /// incompatible version or does not match the configuration.
+    InvalidFormat,
+    /// A batch could not be compressed.
+    Compression(CompressionError),
     /// A batch could not be decompressed correctly.
     /// This also happens only if the file has been corrupted.
-    Decompression,
+    Decompression(CompressionError),
     /// The batch was read back successfully,
     /// but the serialization failed.
     ///
     /// Take a look at the `Serialize` implementation
-    /// of your type `T`.
-    SerializationFailed(bincode::ErrorKind),
+    /// of your type `T`, and whether the configured
+    /// [`Serialization`](crate::Serialization) supports it.
+    SerializationFailed(SerializationError),
+    /// Elements kept in memory have already been moved out by
+    /// the iterator, so they can not be read again after
+    /// [`SwapVecIter::reset`](crate::SwapVecIter::reset).
+    /// They are cloned instead, if `T: Clone`.
+    AlreadyConsumed,
     /// Every other possibility
     Other(std::io::ErrorKind),
 }
@@ -38,6 +52,12 @@ impl From<std::io::Error> for SwapVecError {
 
 impl From<Box<bincode::ErrorKind>> for SwapVecError {
     fn from(value: Box<bincode::ErrorKind>) -> Self {
-        SwapVecError::SerializationFailed(*value)
+        SwapVecError::SerializationFailed(SerializationError::new(value))
+    }
+}
+
+impl From<SerializationError> for SwapVecError {
+    fn from(value: SerializationError) -> Self {
+        SwapVecError::SerializationFailed(value)
     }
 }
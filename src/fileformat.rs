@@ -0,0 +1,533 @@
+//! Layout of the file a `SwapVec` swaps into.
+//!
+//! All integers are little endian.
+//!
+//! ```text
+//! header   magic "SWAPVEC\0", version u16, compression u8,
+//!          checksum u8, serialization u8, flags u8,
+//!          2 reserved bytes, element type tag u64
+//! dict     only if flag 1 is set: length u64, checksum u64,
+//!          compression dictionary shared by all batches
+//! frame*   payload length u64, element count u64,
+//!          checksum u64, payload
+//! index    per batch: frame offset u64, payload length u64,
+//!          element count u64, checksum u64
+//! trailer  index offset u64, batch count u64,
+//!          index checksum u64, magic "SWAPIDX\0"
+//! ```
+//!
+//! The index and trailer are only written once writing is finished.
+//! Without them, the batches can still be found by walking the frames.
+
+use std::{
+    fs::File,
+    hash::Hasher,
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use twox_hash::XxHash64;
+
+use crate::{
+    checkedfile::{BatchInfo, BatchReader, Checksum},
+    error::SwapVecError,
+};
+
+pub(crate) const MAGIC: &[u8; 8] = b"SWAPVEC\0";
+pub(crate) const TRAILER_MAGIC: &[u8; 8] = b"SWAPIDX\0";
+pub(crate) const VERSION: u16 = 1;
+
+pub(crate) const HEADER_LEN: u64 = 24;
+pub(crate) const FRAME_HEADER_LEN: u64 = 24;
+pub(crate) const INDEX_ENTRY_LEN: u64 = 32;
+pub(crate) const TRAILER_LEN: u64 = 32;
+
+/// Header flag, set if a compression dictionary follows the header.
+const FLAG_DICTIONARY: u8 = 1;
+/// Header flag, set if every payload starts with a byte
+/// telling whether the batch has been compressed.
+const FLAG_BATCH_TAGS: u8 = 2;
+
+fn xxhash64(bytes: &[u8]) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(bytes);
+    hasher.finish()
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> u64 {
+    let mut le = [0; 8];
+    le.copy_from_slice(&bytes[at..at + 8]);
+    u64::from_le_bytes(le)
+}
+
+/// Tag identifying the element type of a file.
+/// Based on `std::any::type_name`, so it is meant
+/// to catch mistakes, not to be relied on across compiler versions.
+pub(crate) fn type_tag<T>() -> u64 {
+    xxhash64(std::any::type_name::<T>().as_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FileHeader {
+    pub version: u16,
+    pub compression: u8,
+    pub checksum: Checksum,
+    pub serialization: u8,
+    pub type_tag: u64,
+    pub dictionary: Option<Vec<u8>>,
+    pub batch_tags: bool,
+}
+
+impl FileHeader {
+    /// Length of the header including the dictionary,
+    /// which is where the first frame starts.
+    pub fn len(&self) -> u64 {
+        match &self.dictionary {
+            None => HEADER_LEN,
+            Some(dictionary) => HEADER_LEN + 16 + dictionary.len() as u64,
+        }
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = [0; HEADER_LEN as usize];
+        bytes[0..8].copy_from_slice(MAGIC);
+        bytes[8..10].copy_from_slice(&self.version.to_le_bytes());
+        bytes[10] = self.compression;
+        bytes[11] = self.checksum.id();
+        bytes[12] = self.serialization;
+        if self.dictionary.is_some() {
+            bytes[13] |= FLAG_DICTIONARY;
+        }
+        if self.batch_tags {
+            bytes[13] |= FLAG_BATCH_TAGS;
+        }
+        bytes[16..24].copy_from_slice(&self.type_tag.to_le_bytes());
+        writer.write_all(&bytes)?;
+        if let Some(dictionary) = &self.dictionary {
+            writer.write_all(&(dictionary.len() as u64).to_le_bytes())?;
+            writer.write_all(&xxhash64(dictionary).to_le_bytes())?;
+            writer.write_all(dictionary)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> Result<Self, SwapVecError> {
+        let mut bytes = [0; HEADER_LEN as usize];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[0..8] != MAGIC {
+            return Err(SwapVecError::InvalidFormat);
+        }
+        let version = u16::from_le_bytes([bytes[8], bytes[9]]);
+        if version != VERSION {
+            return Err(SwapVecError::InvalidFormat);
+        }
+        let flags = bytes[13];
+        if flags & !(FLAG_DICTIONARY | FLAG_BATCH_TAGS) != 0 {
+            return Err(SwapVecError::InvalidFormat);
+        }
+        let dictionary = match flags & FLAG_DICTIONARY {
+            0 => None,
+            _ => {
+                let mut section = [0; 16];
+                reader.read_exact(&mut section)?;
+                // Grows with the bytes actually read, so a corrupted
+                // length can not allocate more than the file holds.
+                let len = read_u64(&section, 0);
+                let mut dictionary = Vec::new();
+                reader.by_ref().take(len).read_to_end(&mut dictionary)?;
+                if dictionary.len() as u64 != len {
+                    return Err(SwapVecError::InvalidFormat);
+                }
+                if xxhash64(&dictionary) != read_u64(&section, 8) {
+                    return Err(SwapVecError::WrongChecksum);
+                }
+                Some(dictionary)
+            }
+        };
+        Ok(Self {
+            version,
+            compression: bytes[10],
+            checksum: Checksum::from_id(bytes[11]).ok_or(SwapVecError::InvalidFormat)?,
+            serialization: bytes[12],
+            type_tag: read_u64(&bytes, 16),
+            dictionary,
+            batch_tags: flags & FLAG_BATCH_TAGS != 0,
+        })
+    }
+}
+
+/// Header in front of every batch payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FrameHeader {
+    pub bytes: u64,
+    pub elements: u64,
+    pub hash: u64,
+}
+
+impl FrameHeader {
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut bytes = [0; FRAME_HEADER_LEN as usize];
+        bytes[0..8].copy_from_slice(&self.bytes.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.elements.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.hash.to_le_bytes());
+        writer.write_all(&bytes)
+    }
+
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Self> {
+        let mut bytes = [0; FRAME_HEADER_LEN as usize];
+        reader.read_exact(&mut bytes)?;
+        Ok(Self {
+            bytes: read_u64(&bytes, 0),
+            elements: read_u64(&bytes, 8),
+            hash: read_u64(&bytes, 16),
+        })
+    }
+}
+
+/// Write batch index and trailer, `index_offset` being
+/// the current position of the writer.
+pub(crate) fn write_index(
+    writer: &mut impl Write,
+    batch_infos: &[BatchInfo],
+    index_offset: u64,
+) -> io::Result<()> {
+    let mut index = Vec::with_capacity(batch_infos.len() * INDEX_ENTRY_LEN as usize);
+    for info in batch_infos {
+        index.extend_from_slice(&info.offset.to_le_bytes());
+        index.extend_from_slice(&(info.bytes as u64).to_le_bytes());
+        index.extend_from_slice(&(info.elements as u64).to_le_bytes());
+        index.extend_from_slice(&info.hash.to_le_bytes());
+    }
+    writer.write_all(&index)?;
+    writer.write_all(&index_offset.to_le_bytes())?;
+    writer.write_all(&(batch_infos.len() as u64).to_le_bytes())?;
+    writer.write_all(&xxhash64(&index).to_le_bytes())?;
+    writer.write_all(TRAILER_MAGIC)
+}
+
+/// Parse the trailer, the last `TRAILER_LEN` bytes of a file.
+/// Returns index offset, batch count and index checksum.
+pub(crate) fn parse_trailer(trailer: &[u8; TRAILER_LEN as usize]) -> Option<(u64, u64, u64)> {
+    if &trailer[24..32] != TRAILER_MAGIC {
+        return None;
+    }
+    Some((
+        read_u64(trailer, 0),
+        read_u64(trailer, 8),
+        read_u64(trailer, 16),
+    ))
+}
+
+/// Parse the index, given its bytes and the checksum from the trailer.
+pub(crate) fn parse_index(index: &[u8], hash: u64) -> Result<Vec<BatchInfo>, SwapVecError> {
+    if !index.len().is_multiple_of(INDEX_ENTRY_LEN as usize) {
+        return Err(SwapVecError::InvalidFormat);
+    }
+    if xxhash64(index) != hash {
+        return Err(SwapVecError::WrongChecksum);
+    }
+    Ok(index
+        .chunks_exact(INDEX_ENTRY_LEN as usize)
+        .map(|entry| BatchInfo {
+            offset: read_u64(entry, 0),
+            bytes: read_u64(entry, 8) as usize,
+            elements: read_u64(entry, 16) as usize,
+            hash: read_u64(entry, 24),
+        })
+        .collect())
+}
+
+/// Batches found in a file and the position after the last batch.
+pub(crate) struct BatchIndex {
+    pub batch_infos: Vec<BatchInfo>,
+    pub data_end: u64,
+    pub complete: bool,
+}
+
+/// Read the batch index from the end of the file, or walk
+/// the frames if the index is missing, e.g. because the writing
+/// process has been killed. Incomplete frames at the end are ignored.
+/// `data_start` is the position of the first frame, `checksum`
+/// the one of the file header.
+pub(crate) fn read_batch_index(
+    reader: &mut (impl Read + Seek),
+    data_start: u64,
+    checksum: Checksum,
+) -> Result<BatchIndex, SwapVecError> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len >= data_start + TRAILER_LEN {
+        let mut trailer = [0; TRAILER_LEN as usize];
+        reader.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+        reader.read_exact(&mut trailer)?;
+        if let Some((index_offset, batch_count, hash)) = parse_trailer(&trailer) {
+            // Checked before allocating, the trailer might be corrupted
+            let index_end = batch_count
+                .checked_mul(INDEX_ENTRY_LEN)
+                .and_then(|index_len| index_len.checked_add(index_offset))
+                .and_then(|index_end| index_end.checked_add(TRAILER_LEN));
+            if index_offset < data_start || index_end != Some(len) {
+                return Err(SwapVecError::InvalidFormat);
+            }
+            let mut index = vec![0; (len - TRAILER_LEN - index_offset) as usize];
+            reader.seek(SeekFrom::Start(index_offset))?;
+            reader.read_exact(&mut index)?;
+            let batch_infos = parse_index(&index, hash)?;
+            // Every frame must lie before the index
+            let frames_valid = batch_infos.iter().all(|info| {
+                info.offset >= data_start
+                    && info
+                        .offset
+                        .checked_add(FRAME_HEADER_LEN)
+                        .and_then(|start| start.checked_add(info.bytes as u64))
+                        .is_some_and(|frame_end| frame_end <= index_offset)
+            });
+            if !frames_valid {
+                return Err(SwapVecError::InvalidFormat);
+            }
+            return Ok(BatchIndex {
+                batch_infos,
+                data_end: index_offset,
+                complete: true,
+            });
+        }
+    }
+
+    // The walk stops at the first frame which has not been written
+    // completely. Its header might be anything, e.g. part of an
+    // index which has been cut off, so its payload is checked.
+    let mut batch_infos = Vec::new();
+    let mut position = data_start;
+    let mut payload = Vec::new();
+    reader.seek(SeekFrom::Start(position))?;
+    while position + FRAME_HEADER_LEN <= len {
+        let frame = FrameHeader::read_from(reader)?;
+        let frame_end = (position + FRAME_HEADER_LEN).checked_add(frame.bytes);
+        let Some(frame_end) = frame_end.filter(|&frame_end| frame_end <= len) else {
+            break;
+        };
+        // Batches are never empty
+        if frame.elements == 0 || usize::try_from(frame.elements).is_err() {
+            break;
+        }
+        if checksum != Checksum::None {
+            payload.resize(frame.bytes as usize, 0);
+            reader.read_exact(&mut payload)?;
+            if checksum.of(&payload) != frame.hash {
+                break;
+            }
+        }
+        batch_infos.push(BatchInfo {
+            offset: position,
+            hash: frame.hash,
+            bytes: frame.bytes as usize,
+            elements: frame.elements as usize,
+        });
+        position = reader.seek(SeekFrom::Start(frame_end))?;
+    }
+    Ok(BatchIndex {
+        batch_infos,
+        data_end: position,
+        complete: false,
+    })
+}
+
+/// Summary of a file written by a `SwapVec`,
+/// see [`SwapFileInfo::from_path`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct SwapFileInfo {
+    /// Version of the file format.
+    pub version: u16,
+    /// Id of the compression algorithm the batches
+    /// have been compressed with.
+    pub compression_id: u8,
+    /// Checksum algorithm of the batches.
+    pub checksum: Checksum,
+    /// Size of the compression dictionary stored in the file,
+    /// zero if there is none.
+    pub dictionary_size: usize,
+    /// Number of batches in the file.
+    pub batches: usize,
+    /// Number of elements in all batches.
+    pub elements: usize,
+    /// Size of the file in bytes.
+    pub file_size: u64,
+    /// False if the batch index at the end of the file is missing,
+    /// e.g. because the file is still being written or the
+    /// writing process has been aborted.
+    pub complete: bool,
+}
+
+impl SwapFileInfo {
+    /// Read the header and batch index of a file,
+    /// and verify every batch against its checksum.
+    /// The batches are neither decompressed nor deserialized.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, SwapVecError> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+        let (header, index, mut reader) = BatchReader::open(file)?;
+        while reader.read_batch()?.is_some() {}
+        Ok(Self {
+            version: header.version,
+            compression_id: header.compression,
+            checksum: header.checksum,
+            dictionary_size: header.dictionary.as_ref().map_or(0, |d| d.len()),
+            batches: index.batch_infos.len(),
+            elements: index.batch_infos.iter().map(|b| b.elements).sum(),
+            file_size,
+            complete: index.complete,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Cursor, Seek, SeekFrom};
+
+    use super::*;
+    use crate::checkedfile::BatchWriter;
+
+    fn header() -> FileHeader {
+        FileHeader {
+            version: VERSION,
+            compression: 0,
+            checksum: Checksum::Crc32c,
+            serialization: 0,
+            type_tag: type_tag::<u64>(),
+            dictionary: None,
+            batch_tags: false,
+        }
+    }
+
+    #[test]
+    fn header_roundtrip() {
+        let mut buffer = Vec::new();
+        header().write_to(&mut buffer).unwrap();
+        assert_eq!(buffer.len() as u64, HEADER_LEN);
+        let read_back = FileHeader::read_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(read_back, header());
+
+        buffer[0] = b'X';
+        assert!(matches!(
+            FileHeader::read_from(&mut buffer.as_slice()),
+            Err(SwapVecError::InvalidFormat)
+        ));
+
+        let with_dictionary = FileHeader {
+            dictionary: Some(vec![1, 2, 3]),
+            batch_tags: true,
+            ..header()
+        };
+        let mut buffer = Vec::new();
+        with_dictionary.write_to(&mut buffer).unwrap();
+        assert_eq!(buffer.len() as u64, with_dictionary.len());
+        let read_back = FileHeader::read_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(read_back, with_dictionary);
+    }
+
+    #[test]
+    fn frames_and_index() {
+        let mut writer = BatchWriter::new(Cursor::new(Vec::new()), header()).unwrap();
+        writer.write_batch(&[1, 2, 3], 3).unwrap();
+        writer.write_batch(&[4, 5], 1).unwrap();
+        let mut file = writer.finish().unwrap().0;
+        let bytes = file.get_ref().clone();
+
+        let mut trailer = [0; TRAILER_LEN as usize];
+        trailer.copy_from_slice(&bytes[bytes.len() - TRAILER_LEN as usize..]);
+        let (index_offset, batch_count, hash) = parse_trailer(&trailer).unwrap();
+        assert_eq!(batch_count, 2);
+        let index = &bytes[index_offset as usize..bytes.len() - TRAILER_LEN as usize];
+        let infos = parse_index(index, hash).unwrap();
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[1].elements, 1);
+
+        file.seek(SeekFrom::Start(infos[1].offset)).unwrap();
+        let frame = FrameHeader::read_from(&mut file).unwrap();
+        assert_eq!(frame.bytes, 2);
+        assert_eq!(frame.elements, 1);
+        assert_eq!(frame.hash, Checksum::Crc32c.of(&[4, 5]));
+        let mut payload = [0; 2];
+        file.read_exact(&mut payload).unwrap();
+        assert_eq!(payload, [4, 5]);
+
+        let index = read_batch_index(&mut file, HEADER_LEN, Checksum::Crc32c).unwrap();
+        assert!(index.complete);
+        assert_eq!(index.data_end, index_offset);
+        assert_eq!(index.batch_infos.len(), 2);
+    }
+
+    #[test]
+    fn walk_frames_without_index() {
+        let mut writer = BatchWriter::new(Cursor::new(Vec::new()), header()).unwrap();
+        writer.write_batch(&[1, 2, 3], 3).unwrap();
+        writer.write_batch(&[4, 5], 1).unwrap();
+        let mut bytes = writer.finish().unwrap().0.into_inner();
+        let data_end = HEADER_LEN + 2 * FRAME_HEADER_LEN + 5;
+        // Cut off index and half of the second batch
+        bytes.truncate(data_end as usize - 1);
+
+        let index =
+            read_batch_index(&mut Cursor::new(bytes), HEADER_LEN, Checksum::Crc32c).unwrap();
+        assert!(!index.complete);
+        assert_eq!(index.batch_infos.len(), 1);
+        assert_eq!(index.batch_infos[0].elements, 3);
+        assert_eq!(index.data_end, HEADER_LEN + FRAME_HEADER_LEN + 3);
+    }
+
+    #[test]
+    fn corrupted_lengths() {
+        let mut writer = BatchWriter::new(Cursor::new(Vec::new()), header()).unwrap();
+        writer.write_batch(&[1, 2, 3], 3).unwrap();
+        let bytes = writer.finish().unwrap().0.into_inner();
+        let trailer_start = bytes.len() - TRAILER_LEN as usize;
+        let invalid = |bytes: Vec<u8>| {
+            matches!(
+                read_batch_index(&mut Cursor::new(bytes), HEADER_LEN, Checksum::Crc32c),
+                Err(SwapVecError::InvalidFormat)
+            )
+        };
+
+        let mut batch_count = bytes.clone();
+        batch_count[trailer_start + 8..trailer_start + 16]
+            .copy_from_slice(&(u64::MAX / 2).to_le_bytes());
+        assert!(invalid(batch_count));
+
+        let mut index_offset = bytes.clone();
+        index_offset[trailer_start..trailer_start + 8]
+            .copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(invalid(index_offset));
+
+        // Frame past the index, with a valid index checksum
+        let mut frame_size = bytes[..HEADER_LEN as usize].to_vec();
+        let info = BatchInfo {
+            offset: HEADER_LEN,
+            hash: 0,
+            bytes: usize::MAX / 2,
+            elements: 1,
+        };
+        write_index(&mut frame_size, &[info], HEADER_LEN).unwrap();
+        assert!(invalid(frame_size));
+
+        // Without index, frames are walked until the first invalid one
+        let mut walked = bytes[..trailer_start].to_vec();
+        walked[HEADER_LEN as usize..HEADER_LEN as usize + 8]
+            .copy_from_slice(&u64::MAX.to_le_bytes());
+        let index =
+            read_batch_index(&mut Cursor::new(walked), HEADER_LEN, Checksum::Crc32c).unwrap();
+        assert!(index.batch_infos.is_empty());
+
+        let with_dictionary = FileHeader {
+            dictionary: Some(vec![1, 2, 3]),
+            ..header()
+        };
+        let mut dictionary = Vec::new();
+        with_dictionary.write_to(&mut dictionary).unwrap();
+        dictionary[HEADER_LEN as usize..HEADER_LEN as usize + 8]
+            .copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+        assert!(matches!(
+            FileHeader::read_from(&mut dictionary.as_slice()),
+            Err(SwapVecError::InvalidFormat)
+        ));
+    }
+}
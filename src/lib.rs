@@ -1,13 +1,44 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+#[cfg(feature = "rkyv")]
+mod archived;
+#[cfg(feature = "async")]
+mod asyncswapvec;
+mod backgroundwriter;
+mod batchcache;
+mod batches;
+mod checkedfile;
 mod compression;
 mod error;
+mod fileformat;
+#[cfg(feature = "rayon")]
+mod pariter;
+mod prefetch;
+mod sender;
+mod serialization;
+mod sharded;
+mod sort;
 mod swapvec;
 mod swapveciter;
-mod checkedfile;
 
 pub use self::swapvec::{Compression, CompressionLevel, SwapVec, SwapVecConfig};
-pub use compression::{Compress, CompressBoxedClone};
+#[cfg(feature = "rkyv")]
+pub use archived::{ArchivedSwapVec, ArchivedSwapVecIter};
+#[cfg(feature = "async")]
+pub use asyncswapvec::{AsyncSwapVec, AsyncSwapVecIter};
+pub use checkedfile::Checksum;
+#[allow(deprecated)]
+pub use compression::CompressBoxedClone;
+pub use compression::{BatchCompressionStats, Compress, CompressionError};
 pub use error::SwapVecError;
-pub use swapveciter::SwapVecIter;
+pub use fileformat::SwapFileInfo;
+#[cfg(feature = "rayon")]
+pub use pariter::SwapVecParIter;
+pub use sender::{SwapVecCollector, SwapVecSender};
+pub use serialization::{Serialization, SerializationError};
+pub use sharded::{ShardedSwapVec, ShardedSwapVecIter};
+pub use sort::{SortedSwapVec, SwapVecSortedIter};
+#[cfg(feature = "zstd")]
+pub use swapvec::ZstdDictionary;
+pub use swapveciter::{SwapVecIter, SwapVecRefIter};
@@ -0,0 +1,119 @@
+use std::{collections::VecDeque, iter};
+
+use rayon::iter::{
+    plumbing::UnindexedConsumer, Either, IntoParallelIterator, IntoParallelRefIterator,
+    ParallelIterator,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checkedfile::{BatchInfo, BatchReader, Checksum, FileHandle, SwapFilePath},
+    error::SwapVecError,
+    swapvec::SwapVecConfig,
+    swapveciter::decode_batch,
+};
+
+/// Batches written to the temporary file.
+pub(crate) struct FileBatches<'a> {
+    pub file: &'a SwapFilePath,
+    pub batch_infos: Vec<BatchInfo>,
+    pub checksum: Checksum,
+}
+
+impl FileBatches<'_> {
+    // Open the handle of a worker on first use
+    fn read_batch<T: for<'de> Deserialize<'de>>(
+        &self,
+        config: &SwapVecConfig,
+        reader: &mut Option<BatchReader<FileHandle>>,
+        decompressed: &mut Vec<u8>,
+        batch: usize,
+    ) -> Result<Vec<T>, SwapVecError> {
+        let reader = match reader {
+            Some(reader) => reader,
+            None => reader.insert(BatchReader::new(
+                self.file.open()?,
+                self.batch_infos.clone(),
+                self.checksum,
+            )?),
+        };
+        // Workers mostly read consecutive batches
+        if reader.batch_index() != batch {
+            reader.seek_batch(batch)?;
+        }
+        let buffer = reader.read_batch()?.ok_or(SwapVecError::InvalidFormat)?;
+        decode_batch(config, buffer, decompressed)
+    }
+}
+
+/// Parallel iterator for SwapVec, see [`SwapVec::par_iter`](crate::SwapVec::par_iter).
+///
+/// Batches of the temporary file are split into disjoint ranges,
+/// and every worker reads its ranges with its own handle to the file.
+/// Elements kept in memory are cloned.
+///
+/// A failing batch yields one error in place of its elements,
+/// the other batches are still read.
+pub struct SwapVecParIter<'a, T>
+where
+    for<'de> T: Serialize + Deserialize<'de> + Clone + Send + Sync,
+{
+    file: Option<Result<FileBatches<'a>, SwapVecError>>,
+    last_elements: &'a VecDeque<T>,
+    config: &'a SwapVecConfig,
+}
+
+impl<'a, T> SwapVecParIter<'a, T>
+where
+    for<'de> T: Serialize + Deserialize<'de> + Clone + Send + Sync,
+{
+    pub(crate) fn new(
+        file: Option<Result<FileBatches<'a>, SwapVecError>>,
+        last_elements: &'a VecDeque<T>,
+        config: &'a SwapVecConfig,
+    ) -> Self {
+        Self {
+            file,
+            last_elements,
+            config,
+        }
+    }
+}
+
+impl<T> ParallelIterator for SwapVecParIter<'_, T>
+where
+    for<'de> T: Serialize + Deserialize<'de> + Clone + Send + Sync,
+{
+    type Item = Result<T, SwapVecError>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let config = self.config;
+        let last_elements = self.last_elements.par_iter().cloned().map(Ok);
+        let batches = match self.file {
+            None => return last_elements.drive_unindexed(consumer),
+            Some(Err(err)) => {
+                return rayon::iter::once(Err(err))
+                    .chain(last_elements)
+                    .drive_unindexed(consumer)
+            }
+            Some(Ok(batches)) => batches,
+        };
+        (0..batches.batch_infos.len())
+            .into_par_iter()
+            .map_init(
+                || (None, Vec::new()),
+                |(reader, decompressed), batch| {
+                    batches.read_batch(config, reader, decompressed, batch)
+                },
+            )
+            .flat_map_iter(|batch| match batch {
+                Ok(elements) => Either::Left(elements.into_iter().map(Ok)),
+                Err(err) => Either::Right(iter::once(Err(err))),
+            })
+            .chain(last_elements)
+            .drive_unindexed(consumer)
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    checkedfile::{BatchInfo, BatchReader, Checksum, FileHandle},
+    compression::Compress,
+    error::SwapVecError,
+    swapvec::Compression,
+};
+
+type Decompressed = Result<Vec<u8>, SwapVecError>;
+
+/// Thread reading, checking and decompressing the
+/// next batches, while the previous ones are deserialized.
+///
+/// Batches are read in order, starting at a given batch.
+/// At most `batches` decompressed batches wait to be taken.
+pub(crate) struct Prefetcher {
+    receiver: Option<Receiver<Decompressed>>,
+    handle: Option<JoinHandle<()>>,
+    next_batch: usize,
+}
+
+impl Prefetcher {
+    /// Read `batch_infos[start..]` from its own handle to the file.
+    pub fn spawn(
+        file: FileHandle,
+        batch_infos: Vec<BatchInfo>,
+        checksum: Checksum,
+        compression: Option<Compression>,
+        start: usize,
+        batches: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(batches);
+        let handle = thread::spawn(move || {
+            let result = Self::run(file, batch_infos, checksum, &compression, start, &sender);
+            if let Err(err) = result {
+                // The iterator might have moved on
+                let _ = sender.send(Err(err));
+            }
+        });
+        Self {
+            receiver: Some(receiver),
+            handle: Some(handle),
+            next_batch: start,
+        }
+    }
+
+    fn run(
+        file: FileHandle,
+        batch_infos: Vec<BatchInfo>,
+        checksum: Checksum,
+        compression: &Option<Compression>,
+        start: usize,
+        sender: &SyncSender<Decompressed>,
+    ) -> Result<(), SwapVecError> {
+        let mut reader = BatchReader::new(file, batch_infos, checksum)?;
+        reader.seek_batch(start)?;
+        while let Some(buffer) = reader.read_batch()? {
+            let decompressed = if compression.is_none() {
+                buffer.to_vec()
+            } else {
+                let mut decompressed = Vec::new();
+                compression
+                    .decompress(buffer, &mut decompressed)
+                    .map_err(SwapVecError::Decompression)?;
+                decompressed
+            };
+            if sender.send(Ok(decompressed)).is_err() {
+                // Iterator dropped or restarted elsewhere
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Index of the batch `recv` returns next.
+    pub fn next_batch(&self) -> usize {
+        self.next_batch
+    }
+
+    /// Wait for the next decompressed batch.
+    pub fn recv(&mut self) -> Decompressed {
+        self.next_batch += 1;
+        self.receiver
+            .as_ref()
+            .unwrap()
+            .recv()
+            .map_err(|_| io::Error::other("prefetch thread stopped"))?
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        // Stop reading before the file might be removed
+        drop(self.receiver.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
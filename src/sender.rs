@@ -0,0 +1,230 @@
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    task::Waker,
+    thread::{self, JoinHandle},
+};
+
+#[cfg(feature = "async")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::SwapVecError, swapvec::SwapVec, swapveciter::SwapVecIter};
+
+/// Bounded queue between the senders and the collecting thread.
+/// Senders wait for space, either blocking or as a `Sink`.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // Elements have been queued, or the last sender is gone
+    elements: Condvar,
+    // Space in the queue, or the collector has stopped
+    space: Condvar,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    // Collector stopped, e.g. after an error
+    stopped: bool,
+    // Tasks waiting for space
+    wakers: Vec<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        // A panic while holding the lock leaves the queue consistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn notify_space(&self, state: &mut State<T>) {
+        self.space.notify_all();
+        state.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    /// Take all queued elements, waiting for some.
+    /// `None` once all senders are dropped.
+    fn recv_all(&self) -> Option<VecDeque<T>> {
+        let mut state = self.lock();
+        while state.queue.is_empty() && state.senders > 0 {
+            state = self.elements.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        if state.queue.is_empty() {
+            return None;
+        }
+        let elements = std::mem::take(&mut state.queue);
+        self.notify_space(&mut state);
+        Some(elements)
+    }
+
+    fn stop(&self) {
+        let mut state = self.lock();
+        state.stopped = true;
+        state.queue.clear();
+        self.notify_space(&mut state);
+    }
+}
+
+// Stops the collector when the collecting thread ends,
+// also by a panic, so no sender keeps waiting for space.
+struct StopOnDrop<T>(Arc<Shared<T>>);
+
+impl<T> Drop for StopOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.stop();
+    }
+}
+
+fn stopped() -> SwapVecError {
+    io::Error::other("collector stopped after an error").into()
+}
+
+/// Cloneable handle to push elements into a [`SwapVec`]
+/// from many threads, see [`SwapVec::sender`].
+///
+/// With the `async` feature, it also implements
+/// [`Sink`](https://docs.rs/futures/latest/futures/sink/trait.Sink.html).
+pub struct SwapVecSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> SwapVecSender<T> {
+    /// Queue an element, blocking while the queue is full.
+    ///
+    /// Fails if the collector has stopped after an error,
+    /// which is returned by [`SwapVecCollector::finish`],
+    /// or after a panic, e.g. in the `Serialize` implementation.
+    pub fn send(&self, element: T) -> Result<(), SwapVecError> {
+        let mut state = self.shared.lock();
+        while state.queue.len() >= state.capacity && !state.stopped {
+            state = self
+                .shared
+                .space
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        if state.stopped {
+            return Err(stopped());
+        }
+        state.queue.push_back(element);
+        self.shared.elements.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for SwapVecSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SwapVecSender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.elements.notify_one();
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> futures_sink::Sink<T> for SwapVecSender<T> {
+    type Error = SwapVecError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut state = self.shared.lock();
+        if state.stopped {
+            return Poll::Ready(Err(stopped()));
+        }
+        if state.queue.len() < state.capacity {
+            return Poll::Ready(Ok(()));
+        }
+        state.wakers.push(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn start_send(self: Pin<&mut Self>, element: T) -> Result<(), Self::Error> {
+        let mut state = self.shared.lock();
+        if state.stopped {
+            return Err(stopped());
+        }
+        state.queue.push_back(element);
+        self.shared.elements.notify_one();
+        Ok(())
+    }
+
+    /// Elements are handed over once queued.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Owner of a [`SwapVec`] fed by [`SwapVecSender`]s,
+/// see [`SwapVec::sender`].
+pub struct SwapVecCollector<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    handle: JoinHandle<Result<SwapVec<T>, SwapVecError>>,
+}
+
+impl<T> SwapVecCollector<T>
+where
+    for<'a> T: Serialize + Deserialize<'a> + Send + 'static,
+{
+    pub(crate) fn spawn(mut swapvec: SwapVec<T>, capacity: usize) -> (SwapVecSender<T>, Self) {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity: capacity.max(1),
+                senders: 1,
+                stopped: false,
+                wakers: Vec::new(),
+            }),
+            elements: Condvar::new(),
+            space: Condvar::new(),
+        });
+        let sender = SwapVecSender {
+            shared: shared.clone(),
+        };
+        let handle = thread::spawn(move || {
+            let shared = StopOnDrop(shared);
+            while let Some(elements) = shared.0.recv_all() {
+                swapvec.consume(elements.into_iter())?;
+            }
+            Ok(swapvec)
+        });
+        (sender, Self { handle })
+    }
+
+    /// Wait until all senders have been dropped and all
+    /// elements have been pushed, then iterate over them.
+    ///
+    /// Elements of different senders are interleaved
+    /// in the order they have been queued.
+    pub fn finish(self) -> Result<SwapVecIter<T>, SwapVecError> {
+        self.into_swapvec().map(|swapvec| swapvec.into_iter())
+    }
+
+    /// Like [`SwapVecCollector::finish`], but returns the vector,
+    /// e.g. to push more elements or persist it.
+    pub fn into_swapvec(self) -> Result<SwapVec<T>, SwapVecError> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
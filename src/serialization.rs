@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+/// Error of a serialization format, carried in
+/// [`SwapVecError::SerializationFailed`](crate::SwapVecError::SerializationFailed).
+#[derive(Debug)]
+pub struct SerializationError(Box<dyn std::error::Error + Send + Sync>);
+
+impl SerializationError {
+    /// Wrap the error of a serialization format,
+    /// or a message describing it.
+    pub fn new(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self(error.into())
+    }
+}
+
+impl std::fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SerializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.0.as_ref())
+    }
+}
+
+/// Turn batches of elements into bytes and back.
+///
+/// Implemented by [`Serialization`], which is chosen
+/// with [`SwapVecConfig::serialization`](crate::SwapVecConfig::serialization).
+pub(crate) trait Serializer {
+    /// Serialize a value, e.g. a batch of elements.
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError>;
+    /// Deserialize a value, which has been `serialize()`d before.
+    fn deserialize<T: for<'a> Deserialize<'a>>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<T, SerializationError>;
+    /// Size of the serialized value in bytes.
+    fn serialized_size<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+    ) -> Result<usize, SerializationError> {
+        Ok(self.serialize(value)?.len())
+    }
+    /// Id of the format, stored in the file header.
+    /// A file can only be read with the same id it has been written with.
+    fn format_id(&self) -> u8;
+}
+
+/// Format the elements are serialized with, before being
+/// compressed and written to disk.
+///
+/// Bincode is compact and fast, but does not support
+/// `deserialize_any`, e.g. `#[serde(untagged)]` enums.
+/// Use a self describing format like MessagePack or CBOR then.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Serialization {
+    /// Read more about bincode here: [bincode]
+    ///
+    /// [bincode]: https://github.com/bincode-org/bincode
+    #[default]
+    Bincode,
+    /// Postcard, a compact format like bincode,
+    /// with variable length integers.
+    #[cfg(feature = "postcard")]
+    Postcard,
+    /// MessagePack, with structs serialized as maps.
+    /// Self describing.
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    /// CBOR, self describing.
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl Serializer for Serialization {
+    fn serialize<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, SerializationError> {
+        match self {
+            Serialization::Bincode => bincode::serialize(value).map_err(SerializationError::new),
+            #[cfg(feature = "postcard")]
+            Serialization::Postcard => {
+                postcard::to_allocvec(value).map_err(SerializationError::new)
+            }
+            #[cfg(feature = "msgpack")]
+            Serialization::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(SerializationError::new)
+            }
+            #[cfg(feature = "cbor")]
+            Serialization::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(value, &mut buffer).map_err(SerializationError::new)?;
+                Ok(buffer)
+            }
+        }
+    }
+
+    fn deserialize<T: for<'a> Deserialize<'a>>(
+        &self,
+        bytes: &[u8],
+    ) -> Result<T, SerializationError> {
+        match self {
+            Serialization::Bincode => bincode::deserialize(bytes).map_err(SerializationError::new),
+            #[cfg(feature = "postcard")]
+            Serialization::Postcard => postcard::from_bytes(bytes).map_err(SerializationError::new),
+            #[cfg(feature = "msgpack")]
+            Serialization::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(SerializationError::new)
+            }
+            #[cfg(feature = "cbor")]
+            Serialization::Cbor => ciborium::from_reader(bytes).map_err(SerializationError::new),
+        }
+    }
+
+    fn serialized_size<T: Serialize + ?Sized>(
+        &self,
+        value: &T,
+    ) -> Result<usize, SerializationError> {
+        match self {
+            Serialization::Bincode => bincode::serialized_size(value)
+                .map(|size| size as usize)
+                .map_err(SerializationError::new),
+            #[allow(unreachable_patterns)]
+            _ => Ok(self.serialize(value)?.len()),
+        }
+    }
+
+    fn format_id(&self) -> u8 {
+        match self {
+            Serialization::Bincode => 0,
+            #[cfg(feature = "postcard")]
+            Serialization::Postcard => 1,
+            #[cfg(feature = "msgpack")]
+            Serialization::MessagePack => 2,
+            #[cfg(feature = "cbor")]
+            Serialization::Cbor => 3,
+            // 4 is RKYV_FORMAT_ID
+        }
+    }
+}
+
+/// Format id of batches archived with rkyv by
+/// [`ArchivedSwapVec`](crate::ArchivedSwapVec), instead of a [`Serialization`].
+#[cfg(feature = "rkyv")]
+pub(crate) const RKYV_FORMAT_ID: u8 = 4;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let formats = [
+            Serialization::Bincode,
+            #[cfg(feature = "postcard")]
+            Serialization::Postcard,
+            #[cfg(feature = "msgpack")]
+            Serialization::MessagePack,
+            #[cfg(feature = "cbor")]
+            Serialization::Cbor,
+        ];
+        let value = vec![(1u64, "one".to_string()), (2, "two".to_string())];
+        for format in formats {
+            let bytes = format.serialize(&value).unwrap();
+            assert_eq!(format.serialized_size(&value).unwrap(), bytes.len());
+            let read_back: Vec<(u64, String)> = format.deserialize(&bytes).unwrap();
+            assert_eq!(read_back, value);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    iter::Peekable,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard, Weak,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::SwapVecError,
+    swapvec::{SwapVec, SwapVecConfig},
+    swapveciter::SwapVecIter,
+};
+
+static NEXT_INSTANCE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // Shard of the thread in every instance it has pushed to, so a
+    // thread always pushes to the same shard. Entries of dropped
+    // instances are removed when the thread is assigned a new shard.
+    static THREAD_SHARDS: RefCell<HashMap<u64, (Weak<()>, usize)>> = RefCell::new(HashMap::new());
+}
+
+/// A [`SwapVec`] accepting elements from many threads at once.
+///
+/// Every thread pushes into its own shard, a [`SwapVec`] with its
+/// own batch buffer and temporary file, so producers do not wait
+/// for each other. Threads are assigned to shards in the order
+/// they first push to this vector, so with at least as many shards
+/// as producing threads, no shard is shared and its lock is never
+/// contended.
+///
+/// Every element is stored with its insertion sequence number
+/// (8 more bytes before compression), so the shards can either be
+/// concatenated ([`ShardedSwapVec::into_iter`]) or interleaved
+/// in insertion order ([`ShardedSwapVec::into_interleaved_iter`]).
+/// ```rust
+/// let bigvec = swapvec::ShardedSwapVec::new(4);
+/// std::thread::scope(|s| {
+///     for t in 0..4 {
+///         let bigvec = &bigvec;
+///         s.spawn(move || {
+///             for i in 0..100 {
+///                 bigvec.push(t * 100 + i).unwrap();
+///             }
+///         });
+///     }
+/// });
+/// let sum: i32 = bigvec.into_iter().map(|v| v.unwrap()).sum();
+/// assert_eq!(sum, 79800);
+/// ```
+pub struct ShardedSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    shards: Vec<Mutex<SwapVec<(u64, T)>>>,
+    sequence: AtomicU64,
+    // Key of the thread-local shard assignments, which
+    // are dropped once `alive` has been dropped
+    id: u64,
+    alive: Arc<()>,
+    next_shard: AtomicUsize,
+}
+
+impl<T> ShardedSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    /// Create with `shards` shards (at least one),
+    /// each with the default configuration.
+    pub fn new(shards: usize) -> Self {
+        Self::with_config(SwapVecConfig::default(), shards)
+    }
+
+    /// Create with `shards` shards (at least one),
+    /// each with a copy of `config`.
+    ///
+    /// Limits apply per shard, e.g. every shard
+    /// keeps up to `swap_after` elements in memory.
+    pub fn with_config(config: SwapVecConfig, shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| Mutex::new(SwapVec::with_config(config.clone())))
+                .collect(),
+            sequence: AtomicU64::new(0),
+            id: NEXT_INSTANCE_ID.fetch_add(1, Ordering::Relaxed),
+            alive: Arc::new(()),
+            next_shard: AtomicUsize::new(0),
+        }
+    }
+
+    // Shard of the calling thread, assigned on its first push
+    fn thread_shard(&self) -> usize {
+        THREAD_SHARDS.with(|shards| {
+            let mut shards = shards.borrow_mut();
+            if let Some((_, shard)) = shards.get(&self.id) {
+                return *shard;
+            }
+            shards.retain(|_, (alive, _)| alive.strong_count() > 0);
+            let shard = self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+            shards.insert(self.id, (Arc::downgrade(&self.alive), shard));
+            shard
+        })
+    }
+
+    fn lock(shard: &Mutex<SwapVec<(u64, T)>>) -> MutexGuard<'_, SwapVec<(u64, T)>> {
+        // A failed push has already returned its error
+        shard.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Push a single element into the shard of the calling thread.
+    /// Might return an error, due to possibly triggered batch flush (IO).
+    pub fn push(&self, element: T) -> Result<(), SwapVecError> {
+        let mut shard = Self::lock(&self.shards[self.thread_shard()]);
+        // Numbered while holding the lock,
+        // so every shard stays in insertion order.
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        shard.push((sequence, element))
+    }
+
+    /// Give away an entire iterator for consumption.
+    pub fn consume(&self, it: impl Iterator<Item = T>) -> Result<(), SwapVecError> {
+        for element in it {
+            self.push(element)?;
+        }
+        Ok(())
+    }
+
+    /// Total number of elements in all shards.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| Self::lock(s).len()).sum()
+    }
+
+    /// Returns `true` if no elements have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Iterate over all elements in insertion order,
+    /// merging the shards by sequence number.
+    ///
+    /// Only pushes completed before each other are ordered,
+    /// concurrent pushes may appear either way.
+    pub fn into_interleaved_iter(self) -> ShardedSwapVecIter<T> {
+        self.into_sharded_iter(true)
+    }
+
+    fn into_sharded_iter(self, interleaved: bool) -> ShardedSwapVecIter<T> {
+        ShardedSwapVecIter {
+            shards: self
+                .shards
+                .into_iter()
+                .map(|s| {
+                    s.into_inner()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .into_iter()
+                        .peekable()
+                })
+                .collect(),
+            interleaved,
+            current: 0,
+        }
+    }
+}
+
+impl<T> IntoIterator for ShardedSwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    type Item = Result<T, SwapVecError>;
+    type IntoIter = ShardedSwapVecIter<T>;
+
+    /// Iterate over all elements shard by shard.
+    /// Elements pushed by one thread keep their order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_sharded_iter(false)
+    }
+}
+
+/// Iterator for [`ShardedSwapVec`], either concatenating
+/// or interleaving the shards.
+///
+/// Items might be read from disk,
+/// so every item is wrapped in a `Result`.
+/// The iteration ends after the first error.
+///
+/// Dropping the iterator removes the temporary files, if existing.
+pub struct ShardedSwapVecIter<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    shards: Vec<Peekable<SwapVecIter<(u64, T)>>>,
+    interleaved: bool,
+    // Shard being read when concatenating
+    current: usize,
+}
+
+impl<T> ShardedSwapVecIter<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    // Shard with the lowest sequence number next,
+    // or the first shard failing to read.
+    fn next_interleaved(&mut self) -> Option<usize> {
+        let mut lowest: Option<(u64, usize)> = None;
+        for (index, shard) in self.shards.iter_mut().enumerate() {
+            match shard.peek() {
+                Some(Err(_)) => return Some(index),
+                Some(Ok((sequence, _))) if lowest.is_none_or(|(l, _)| *sequence < l) => {
+                    lowest = Some((*sequence, index));
+                }
+                _ => {}
+            }
+        }
+        lowest.map(|(_, index)| index)
+    }
+}
+
+impl<T> Iterator for ShardedSwapVecIter<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    type Item = Result<T, SwapVecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = if self.interleaved {
+            let index = self.next_interleaved()?;
+            self.shards[index].next()?
+        } else {
+            loop {
+                let shard = self.shards.get_mut(self.current)?;
+                match shard.next() {
+                    Some(next) => break next,
+                    None => self.current += 1,
+                }
+            }
+        };
+        match next {
+            Ok((_, element)) => Some(Ok(element)),
+            Err(err) => {
+                self.shards.clear();
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.shards.iter().map(|s| s.len()).sum();
+        (len, Some(len))
+    }
+}
+
+impl<T> ExactSizeIterator for ShardedSwapVecIter<T> where for<'a> T: Serialize + Deserialize<'a> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn forget_dropped_instances() {
+        for i in 0..100u64 {
+            let bigvec = ShardedSwapVec::new(2);
+            bigvec.push(i).unwrap();
+        }
+        let kept = ShardedSwapVec::new(2);
+        kept.push(0u64).unwrap();
+        // Only the live instance is left
+        assert_eq!(THREAD_SHARDS.with(|shards| shards.borrow().len()), 1);
+    }
+}
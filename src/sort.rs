@@ -0,0 +1,558 @@
+use std::{cmp::Ordering, iter, ops::Range};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    batches::{write_compressed, SizeEstimator},
+    checkedfile::{BatchInfo, BatchReader, BatchWriter, Checksum, FileHandle, SwapFilePath},
+    error::SwapVecError,
+    serialization::Serializer,
+    swapvec::SwapVecConfig,
+    swapveciter::{decode_batch, SwapVecIter},
+};
+
+/// Sorted runs in one temporary file,
+/// each a range of consecutive batches.
+struct RunFile {
+    // Removes the temporary file on drop
+    path: SwapFilePath,
+    batch_infos: Vec<BatchInfo>,
+    checksum: Checksum,
+    runs: Vec<Range<usize>>,
+}
+
+impl RunFile {
+    /// Read a run with its own handle to the file.
+    fn open_run<T>(&self, run: Range<usize>) -> Result<Run<T>, SwapVecError> {
+        let file = self.path.open()?;
+        Ok(Run::File {
+            reader: BatchReader::new(file, self.batch_infos[run].to_vec(), self.checksum)?,
+            batch: Vec::new().into_iter(),
+        })
+    }
+}
+
+struct RunWriter {
+    writer: BatchWriter<FileHandle>,
+    path: SwapFilePath,
+    runs: Vec<Range<usize>>,
+    compression_buffers: Vec<Vec<u8>>,
+}
+
+impl RunWriter {
+    fn new<T>(config: &SwapVecConfig) -> Result<Self, SwapVecError> {
+        let (file, path) = config.create_tempfile()?;
+        Ok(Self {
+            writer: BatchWriter::new(file, config.file_header::<T>())?,
+            path,
+            runs: Vec::new(),
+            compression_buffers: Vec::new(),
+        })
+    }
+
+    /// Write sorted elements as the next run,
+    /// stopping at the first error.
+    fn write_run<T: Serialize>(
+        &mut self,
+        config: &SwapVecConfig,
+        size_estimator: &Option<SizeEstimator<T>>,
+        elements: impl Iterator<Item = Result<T, SwapVecError>>,
+    ) -> Result<(), SwapVecError> {
+        let start = self.writer.batch_count();
+        let mut batch = Vec::new();
+        let mut bytes = 0;
+        for element in elements {
+            let element = element?;
+            if config.batch_bytes.is_some() {
+                bytes += estimate_size(config, size_estimator, &element);
+            }
+            batch.push(element);
+            if batch.len() >= config.batch_size
+                || config.batch_bytes.is_some_and(|limit| bytes >= limit)
+            {
+                self.write_batch(config, &batch)?;
+                batch.clear();
+                bytes = 0;
+            }
+        }
+        if !batch.is_empty() {
+            self.write_batch(config, &batch)?;
+        }
+        self.runs.push(start..self.writer.batch_count());
+        Ok(())
+    }
+
+    fn write_batch<T: Serialize>(
+        &mut self,
+        config: &SwapVecConfig,
+        batch: &[T],
+    ) -> Result<(), SwapVecError> {
+        let serialized = (config.serialization.serialize(batch)?, batch.len());
+        write_compressed(
+            &mut self.writer,
+            &config.compression,
+            &[serialized],
+            &mut self.compression_buffers,
+            &mut Vec::new(),
+        )
+    }
+
+    fn finish(self) -> RunFile {
+        RunFile {
+            batch_infos: self.writer.batch_infos().to_vec(),
+            checksum: self.writer.checksum(),
+            path: self.path,
+            runs: self.runs,
+        }
+    }
+}
+
+enum Run<T> {
+    File {
+        reader: BatchReader<FileHandle>,
+        batch: std::vec::IntoIter<T>,
+    },
+    // Last elements, never written to a run file
+    Memory(std::vec::IntoIter<T>),
+}
+
+impl<T: for<'a> Deserialize<'a>> Run<T> {
+    fn next(
+        &mut self,
+        config: &SwapVecConfig,
+        decompressed: &mut Vec<u8>,
+    ) -> Result<Option<T>, SwapVecError> {
+        let (reader, batch) = match self {
+            Run::Memory(elements) => return Ok(elements.next()),
+            Run::File { reader, batch } => (reader, batch),
+        };
+        loop {
+            if let Some(element) = batch.next() {
+                return Ok(Some(element));
+            }
+            match reader.read_batch()? {
+                None => return Ok(None),
+                Some(buffer) => *batch = decode_batch(config, buffer, decompressed)?.into_iter(),
+            }
+        }
+    }
+}
+
+/// K-way merge of sorted runs, with a binary heap
+/// of the runs ordered by their next element.
+struct Merge<T> {
+    runs: Vec<Run<T>>,
+    heads: Vec<Option<T>>,
+    // Indices of the runs not exhausted yet
+    heap: Vec<usize>,
+    decompressed: Vec<u8>,
+}
+
+impl<T: for<'a> Deserialize<'a>> Merge<T> {
+    fn new<F: FnMut(&T, &T) -> Ordering>(
+        mut runs: Vec<Run<T>>,
+        config: &SwapVecConfig,
+        compare: &mut F,
+    ) -> Result<Self, SwapVecError> {
+        let mut decompressed = Vec::new();
+        let heads = runs
+            .iter_mut()
+            .map(|run| run.next(config, &mut decompressed))
+            .collect::<Result<Vec<_>, _>>()?;
+        let heap = (0..runs.len()).filter(|&r| heads[r].is_some()).collect();
+        let mut merge = Self {
+            runs,
+            heads,
+            heap,
+            decompressed,
+        };
+        for i in (0..merge.heap.len() / 2).rev() {
+            merge.sift_down(i, compare);
+        }
+        Ok(merge)
+    }
+
+    fn empty() -> Self {
+        Self {
+            runs: Vec::new(),
+            heads: Vec::new(),
+            heap: Vec::new(),
+            decompressed: Vec::new(),
+        }
+    }
+
+    fn next<F: FnMut(&T, &T) -> Ordering>(
+        &mut self,
+        config: &SwapVecConfig,
+        compare: &mut F,
+    ) -> Result<Option<T>, SwapVecError> {
+        let Some(&run) = self.heap.first() else {
+            return Ok(None);
+        };
+        let element = self.heads[run].take();
+        self.heads[run] = self.runs[run].next(config, &mut self.decompressed)?;
+        if self.heads[run].is_none() {
+            self.heap.swap_remove(0);
+        }
+        self.sift_down(0, compare);
+        Ok(element)
+    }
+
+    fn less<F: FnMut(&T, &T) -> Ordering>(&self, a: usize, b: usize, compare: &mut F) -> bool {
+        let (head_a, head_b) = (self.heads[a].as_ref(), self.heads[b].as_ref());
+        match compare(head_a.unwrap(), head_b.unwrap()) {
+            Ordering::Less => true,
+            // The earlier run first, keeping the sort stable
+            Ordering::Equal => a < b,
+            Ordering::Greater => false,
+        }
+    }
+
+    fn sift_down<F: FnMut(&T, &T) -> Ordering>(&mut self, mut i: usize, compare: &mut F) {
+        loop {
+            let mut smallest = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len()
+                    && self.less(self.heap[child], self.heap[smallest], compare)
+                {
+                    smallest = child;
+                }
+            }
+            if smallest == i {
+                return;
+            }
+            self.heap.swap(i, smallest);
+            i = smallest;
+        }
+    }
+}
+
+// Size of an element, estimated as by `SwapVec`
+fn estimate_size<T: Serialize>(
+    config: &SwapVecConfig,
+    size_estimator: &Option<SizeEstimator<T>>,
+    element: &T,
+) -> usize {
+    match size_estimator.as_ref() {
+        Some(estimator) => estimator(element),
+        None => config.serialization.serialized_size(element).unwrap_or(0),
+    }
+}
+
+/// Runs of up to `swap_after` elements or `swap_after_bytes`,
+/// sorted and written to a file once full, except for the last one.
+struct Runs<T> {
+    run: Vec<T>,
+    // Estimated bytes of the run, only tracked
+    // if the configuration has byte limits
+    bytes: usize,
+    size_estimator: Option<SizeEstimator<T>>,
+    writer: Option<RunWriter>,
+}
+
+impl<T> Runs<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    fn new(size_estimator: Option<SizeEstimator<T>>) -> Self {
+        Self {
+            run: Vec::new(),
+            bytes: 0,
+            size_estimator,
+            writer: None,
+        }
+    }
+
+    fn set_size_estimator(&mut self, config: &SwapVecConfig, estimator: SizeEstimator<T>) {
+        self.size_estimator = Some(estimator);
+        if config.measures_bytes() {
+            self.bytes = self
+                .run
+                .iter()
+                .map(|element| estimate_size(config, &self.size_estimator, element))
+                .sum();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.writer.as_ref().map_or(0, |w| w.writer.element_count()) + self.run.len()
+    }
+
+    fn push<F: FnMut(&T, &T) -> Ordering>(
+        &mut self,
+        config: &SwapVecConfig,
+        element: T,
+        compare: &mut F,
+        stable: bool,
+    ) -> Result<(), SwapVecError> {
+        if config.measures_bytes() {
+            self.bytes += estimate_size(config, &self.size_estimator, &element);
+        }
+        self.run.push(element);
+        let run_full = self.run.len() > config.swap_after.max(config.batch_size).max(1)
+            || config
+                .swap_after_bytes
+                .is_some_and(|limit| self.bytes > limit);
+        if run_full {
+            sort_run(&mut self.run, compare, stable);
+            if self.writer.is_none() {
+                self.writer = Some(RunWriter::new::<T>(config)?);
+            }
+            let writer = self.writer.as_mut().unwrap();
+            writer.write_run(config, &self.size_estimator, self.run.drain(..).map(Ok))?;
+            self.bytes = 0;
+        }
+        Ok(())
+    }
+
+    /// Sort the last run, then merge the runs of the file
+    /// until at most `merge_fan_in` runs are left,
+    /// including the last one.
+    fn merge<F: FnMut(&T, &T) -> Ordering>(
+        mut self,
+        config: &SwapVecConfig,
+        compare: &mut F,
+        stable: bool,
+    ) -> Result<(Option<RunFile>, Vec<Run<T>>), SwapVecError> {
+        sort_run(&mut self.run, compare, stable);
+        let last_run = Run::Memory(self.run.into_iter());
+        let Some(writer) = self.writer else {
+            return Ok((None, vec![last_run]));
+        };
+
+        let fan_in = config.merge_fan_in.max(2);
+        let mut file = writer.finish();
+        while file.runs.len() + 1 > fan_in {
+            let mut writer = RunWriter::new::<T>(config)?;
+            for group in file.runs.chunks(fan_in) {
+                let runs = group
+                    .iter()
+                    .map(|run| file.open_run(run.clone()))
+                    .collect::<Result<_, _>>()?;
+                let mut merge = Merge::new(runs, config, compare)?;
+                let merged = iter::from_fn(|| merge.next(config, compare).transpose());
+                writer.write_run(config, &self.size_estimator, merged)?;
+            }
+            // Removes the previous file
+            file = writer.finish();
+        }
+        let mut runs = file
+            .runs
+            .iter()
+            .map(|run| file.open_run(run.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        runs.push(last_run);
+        Ok((Some(file), runs))
+    }
+
+    fn into_sorted_iter<F: FnMut(&T, &T) -> Ordering>(
+        self,
+        config: SwapVecConfig,
+        mut compare: F,
+        stable: bool,
+    ) -> SwapVecSortedIter<T, F> {
+        let remaining = self.len();
+        let result = self
+            .merge(&config, &mut compare, stable)
+            .and_then(|(file, runs)| Ok((Merge::new(runs, &config, &mut compare)?, file)));
+        SwapVecSortedIter::new(config, compare, remaining, result)
+    }
+}
+
+fn sort_run<T, F: FnMut(&T, &T) -> Ordering>(run: &mut [T], compare: &mut F, stable: bool) {
+    if stable {
+        run.sort_by(compare);
+    } else {
+        run.sort_unstable_by(compare);
+    }
+}
+
+pub(crate) fn sort<T, F>(
+    config: SwapVecConfig,
+    size_estimator: Option<SizeEstimator<T>>,
+    mut elements: SwapVecIter<T>,
+    mut compare: F,
+    stable: bool,
+) -> SwapVecSortedIter<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let remaining = elements.len();
+    let mut runs = Runs::new(size_estimator);
+    let pushed =
+        elements.try_for_each(|element| runs.push(&config, element?, &mut compare, stable));
+    match pushed {
+        Ok(()) => runs.into_sorted_iter(config, compare, stable),
+        Err(err) => SwapVecSortedIter::new(config, compare, remaining, Err(err)),
+    }
+}
+
+/// A vector which sorts its elements while swapping them to disk,
+/// without writing them in insertion order first.
+///
+/// Runs of up to `swap_after` elements, or `swap_after_bytes` if
+/// configured, are sorted with `compare` and written to the
+/// temporary file once full, as by
+/// [`SwapVec::sort_by`](crate::SwapVec::sort_by), which needs a second
+/// file of the same size for the runs. The sort is stable.
+/// ```rust
+/// let mut sorted = swapvec::SortedSwapVec::new(swapvec::SwapVecConfig::default(), u64::cmp);
+/// sorted.consume((0..9999u64).rev()).unwrap();
+/// let mut iterator = sorted.into_sorted_iter();
+/// assert_eq!(iterator.next().unwrap().unwrap(), 0);
+/// ```
+pub struct SortedSwapVec<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    runs: Runs<T>,
+    config: SwapVecConfig,
+    compare: F,
+}
+
+impl<T, F> SortedSwapVec<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    /// Sort the elements with `compare`, e.g. `Ord::cmp`.
+    pub fn new(config: SwapVecConfig, compare: F) -> Self {
+        Self {
+            runs: Runs::new(None),
+            config,
+            compare,
+        }
+    }
+
+    /// Set how the memory size of an element in bytes is estimated,
+    /// see [`SwapVec::set_size_estimator`](crate::SwapVec::set_size_estimator).
+    pub fn set_size_estimator(&mut self, estimator: impl Fn(&T) -> usize + Send + Sync + 'static) {
+        self.runs
+            .set_size_estimator(&self.config, Box::new(estimator));
+    }
+
+    /// Push a single element.
+    /// Might return an error, due to writing a full run (IO).
+    pub fn push(&mut self, element: T) -> Result<(), SwapVecError> {
+        self.runs
+            .push(&self.config, element, &mut self.compare, true)
+    }
+
+    /// Give away an entire iterator for consumption.
+    /// Might return an error, due to writing a full run (IO).
+    pub fn consume(&mut self, it: impl Iterator<Item = T>) -> Result<(), SwapVecError> {
+        for element in it {
+            self.push(element)?;
+        }
+        Ok(())
+    }
+
+    /// Total number of elements, both in the file and in memory.
+    pub fn len(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Returns `true` if no elements have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if a run has been written to the temporary file.
+    pub fn written_to_file(&self) -> bool {
+        self.runs.writer.is_some()
+    }
+
+    /// Merge the runs and iterate over the sorted elements.
+    pub fn into_sorted_iter(self) -> SwapVecSortedIter<T, F> {
+        self.runs.into_sorted_iter(self.config, self.compare, true)
+    }
+}
+
+/// Iterator over the sorted elements of a SwapVec,
+/// see [`SwapVec::sort_by`](crate::SwapVec::sort_by)
+/// and [`SortedSwapVec`].
+///
+/// Items might be read from disk,
+/// so every item is wrapped in a `Result`.
+/// The iteration ends after the first error.
+///
+/// Dropping the iterator removes the temporary file, if existing.
+pub struct SwapVecSortedIter<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    new_error: Option<SwapVecError>,
+    merge: Merge<T>,
+    // Removes the temporary file on drop, after closing the runs
+    _run_file: Option<RunFile>,
+    remaining: usize,
+    config: SwapVecConfig,
+    compare: F,
+}
+
+impl<T, F> SwapVecSortedIter<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    fn new(
+        config: SwapVecConfig,
+        compare: F,
+        remaining: usize,
+        merge: Result<(Merge<T>, Option<RunFile>), SwapVecError>,
+    ) -> Self {
+        let (new_error, merge, run_file) = match merge {
+            Ok((merge, file)) => (None, merge, file),
+            Err(err) => (Some(err), Merge::empty(), None),
+        };
+        Self {
+            remaining: if new_error.is_some() { 0 } else { remaining },
+            new_error,
+            merge,
+            _run_file: run_file,
+            config,
+            compare,
+        }
+    }
+}
+
+impl<T, F> Iterator for SwapVecSortedIter<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    type Item = Result<T, SwapVecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = match self.new_error.take() {
+            Some(err) => Err(err),
+            None => self.merge.next(&self.config, &mut self.compare),
+        };
+        match next {
+            Ok(element) => {
+                self.remaining = self.remaining.saturating_sub(1);
+                element.map(Ok)
+            }
+            Err(err) => {
+                self.merge = Merge::empty();
+                self.remaining = 0;
+                Some(Err(err))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining + usize::from(self.new_error.is_some());
+        (len, Some(len))
+    }
+}
+
+impl<T, F> ExactSizeIterator for SwapVecSortedIter<T, F>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+}
@@ -1,16 +1,28 @@
 use std::{
-    collections::VecDeque,
+    cmp::Ordering,
     fmt::Debug,
-    fs::File,
+    fs::OpenOptions,
+    io::{Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "rayon")]
+use crate::pariter::{FileBatches, SwapVecParIter};
 use crate::{
-    checkedfile::BatchWriter,
-    compression::{Compress, CompressBoxedClone},
+    backgroundwriter::WriterThread,
+    batchcache::BatchCache,
+    batches::{write_compressed, PendingElements, SerializedBatch, WriteBatches},
+    checkedfile::{BatchInfo, BatchReader, BatchWriter, Checksum, FileHandle, SwapFilePath},
+    compression::{self, BatchCompressionStats, Compress},
     error::SwapVecError,
-    swapveciter::SwapVecIter,
+    fileformat::{self, FileHeader},
+    sender::{SwapVecCollector, SwapVecSender},
+    serialization::{Serialization, Serializer},
+    sort::{self, SwapVecSortedIter},
+    swapveciter::{decode_batch, SwapVecIter, SwapVecRefIter},
 };
 
 /// Set compression level of the compression
@@ -28,31 +40,81 @@ pub enum CompressionLevel {
     /// Useful for easily compressable data with
     /// many repetitions.
     Fast,
+    /// Level as understood by the algorithm, e.g. `1..=22` for zstd.
+    /// Clamped to `0..=10` for deflate, ignored by LZ4.
+    Numeric(i32),
 }
 
 /// Configure compression for the temporary
 /// file into which your data might be swapped out.  
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 #[non_exhaustive]
 pub enum Compression {
     /// Read more about LZ4 here: [LZ4]
     /// [LZ4]: https://github.com/lz4/lz4
+    #[cfg(feature = "lz4")]
     Lz4,
     /// Deflate, mostly known from gzip.
+    #[cfg(feature = "deflate")]
     Deflate(CompressionLevel),
+    /// Zstandard, read more here: [zstd]
+    /// [zstd]: https://github.com/facebook/zstd
+    ///
+    /// Every batch is compressed on its own, so small batches
+    /// compress a lot better with a dictionary.
+    #[cfg(feature = "zstd")]
+    Zstd {
+        /// Compression level
+        level: CompressionLevel,
+        /// Dictionary shared by all batches
+        dictionary: ZstdDictionary,
+    },
+    /// Try `compression` on every batch, but store the batch
+    /// uncompressed if its compressed size is above `max_ratio`
+    /// of the uncompressed size, e.g. `0.9`.
+    /// Useful if some of the data is already compressed.
+    ///
+    /// Every batch is tagged with one byte telling
+    /// whether it has been compressed.
+    Adaptive {
+        /// Algorithm to try on every batch
+        compression: Box<Compression>,
+        /// Highest compressed to uncompressed size ratio
+        /// to keep the compressed batch
+        max_ratio: f64,
+    },
     /// Provide your own compression algortihm by implementing
     /// `Compress`.
-    Custom(Box<dyn CompressBoxedClone>),
+    ///
+    /// The algorithm is shared, not cloned, between the configuration
+    /// of a `SwapVec` and its iterators, so it can keep state.
+    Custom(Arc<dyn Compress + Send + Sync>),
 }
 
-impl Clone for Compression {
-    fn clone(&self) -> Self {
-        match &self {
-            Self::Lz4 => Self::Lz4,
-            Self::Deflate(n) => Self::Deflate(*n),
-            Self::Custom(x) => Self::Custom(x.boxed_clone()),
-        }
-    }
+/// Dictionary for [`Compression::Zstd`].
+///
+/// The dictionary is stored in the file, so
+/// reopening a file does not require passing it again.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Debug, Default)]
+pub enum ZstdDictionary {
+    /// Compress without dictionary.
+    #[default]
+    None,
+    /// Use the given dictionary, e.g. trained on earlier data
+    /// with [`zstd::dict::from_samples`](https://docs.rs/zstd/latest/zstd/dict/fn.from_samples.html).
+    Given(Vec<u8>),
+    /// Keep the first `batches` batches in memory, and train a
+    /// dictionary of at most `max_size` bytes on them,
+    /// before creating the file.
+    /// Falls back to no dictionary, if training fails,
+    /// e.g. because there is too little data.
+    Train {
+        /// Number of batches used for training
+        batches: usize,
+        /// Maximum size of the dictionary in bytes
+        max_size: usize,
+    },
 }
 
 /// Configure when and how the vector should swap.
@@ -63,7 +125,12 @@ impl Clone for Compression {
 /// Keep in mind, that if the temporary file exists,
 /// after ever batch_size elements, at least one write (syscall)
 /// will happen.
-#[derive(Debug)]
+///
+/// Besides element counts, limits can be given in bytes
+/// (`swap_after_bytes`, `batch_bytes`). The size of an element
+/// is estimated, see [`SwapVec::set_size_estimator`].
+/// Whichever limit is hit first triggers a swap or ends a batch.
+#[derive(Clone, Debug)]
 pub struct SwapVecConfig {
     /// The vector will create a temporary file and starting to
     /// swap after so many elements.
@@ -90,6 +157,93 @@ pub struct SwapVecConfig {
     ///
     /// Default: No compression
     pub compression: Option<Compression>,
+    /// Checksum used to verify every batch read back
+    /// from the temporary file.
+    ///
+    /// Default: `Checksum::XxHash64`
+    pub checksum: Checksum,
+    /// Format the elements are serialized with.
+    ///
+    /// Default: `Serialization::Bincode`
+    pub serialization: Serialization,
+    /// The vector will create a temporary file and start to
+    /// swap as soon as the elements in memory are estimated
+    /// to take more than so many bytes.
+    /// Also afterwards, batches are written until the elements
+    /// in memory fit again, so this bounds the memory used,
+    /// even if no batch is full yet.
+    ///
+    /// Default: None (only `swap_after` applies)
+    pub swap_after_bytes: Option<usize>,
+    /// A batch is written as soon as the elements in memory are
+    /// estimated to take more than so many bytes. The batch
+    /// will then contain at most `batch_size` elements and
+    /// roughly `batch_bytes` bytes, but at least one element.
+    ///
+    /// Default: None (only `batch_size` applies)
+    pub batch_bytes: Option<usize>,
+    /// Directory in which the temporary file will be created.
+    /// Make sure it is writable and has enough space.
+    ///
+    /// Default: None, which uses [`std::env::temp_dir()`]
+    /// (respecting `TMPDIR` on Unix)
+    pub temp_dir: Option<PathBuf>,
+    /// Name prefix of the temporary file.
+    /// A random part is appended to keep the name unique.
+    ///
+    /// Default: `"swapvec-"`
+    pub file_prefix: String,
+    /// Name suffix of the temporary file, e.g. an extension.
+    ///
+    /// Default: Empty
+    pub file_suffix: String,
+    /// Keep the name of the temporary file, so it can be found
+    /// with [`SwapVec::file_path`], e.g. for diagnostics.
+    /// A named file is removed on drop, even when panicking,
+    /// but not if the process is killed or aborts.
+    ///
+    /// Default: false, the file is removed right after creation
+    /// and only kept open, so it never outlives the process.
+    pub named_file: bool,
+    /// How many decoded batches [`SwapVec::get`] keeps in memory,
+    /// to speed up access to elements close to each other.
+    ///
+    /// Default: 4
+    pub batch_cache_size: usize,
+    /// Compress and write batches on a background thread,
+    /// so pushing elements does not wait for the disk.
+    /// Batches are still serialized on the pushing thread.
+    ///
+    /// At most so many batches wait to be written,
+    /// pushing more blocks until the thread catches up.
+    /// An error of the thread is returned by the next
+    /// [`SwapVec::push`], or by the iterator.
+    ///
+    /// Default: None (write on the pushing thread)
+    pub background_writer: Option<usize>,
+    /// While iterating with [`SwapVecIter`] from the front,
+    /// read and decompress up to so many batches ahead
+    /// on a helper thread, so disk and CPU are busy at the same time.
+    /// Batches are still deserialized on the iterating thread.
+    ///
+    /// Default: None (read when needed)
+    pub prefetch: Option<usize>,
+    /// Compress so many batches at once, each on its own thread.
+    /// Useful for slow compression like `Deflate(CompressionLevel::Slow)`.
+    ///
+    /// As many batches are kept in memory before writing them,
+    /// so memory usage grows accordingly.
+    /// Batches are still written in order.
+    ///
+    /// Default: 1
+    pub compression_threads: usize,
+    /// How many sorted runs are merged at once by
+    /// [`SwapVec::sort_by`] and the other sorting methods.
+    /// Every run being merged keeps one batch in memory,
+    /// with more runs, they are merged in several passes.
+    ///
+    /// Default: 16
+    pub merge_fan_in: usize,
 }
 
 impl Default for SwapVecConfig {
@@ -98,10 +252,90 @@ impl Default for SwapVecConfig {
             swap_after: 32 * 1024 * 1024,
             batch_size: 32 * 1024,
             compression: None,
+            checksum: Checksum::default(),
+            serialization: Serialization::default(),
+            swap_after_bytes: None,
+            batch_bytes: None,
+            temp_dir: None,
+            file_prefix: "swapvec-".to_string(),
+            file_suffix: String::new(),
+            named_file: false,
+            batch_cache_size: 4,
+            background_writer: None,
+            prefetch: None,
+            compression_threads: 1,
+            merge_fan_in: 16,
         }
     }
 }
 
+impl SwapVecConfig {
+    pub(crate) fn measures_bytes(&self) -> bool {
+        self.swap_after_bytes.is_some() || self.batch_bytes.is_some()
+    }
+
+    pub(crate) fn create_tempfile(&self) -> Result<(FileHandle, SwapFilePath), SwapVecError> {
+        let dir = self.temp_dir.clone().unwrap_or_else(std::env::temp_dir);
+        let tempfile = tempfile::Builder::new()
+            .prefix(&self.file_prefix)
+            .suffix(&self.file_suffix)
+            .tempfile_in(dir)?;
+        if self.named_file {
+            let (file, path) = tempfile.into_parts();
+            return Ok((FileHandle::new(file), SwapFilePath::Temporary(path)));
+        }
+        let file = FileHandle::new(tempfile.into_file());
+        let handle = file.try_clone()?;
+        Ok((file, SwapFilePath::Unlinked(handle)))
+    }
+
+    pub(crate) fn file_header<T>(&self) -> FileHeader {
+        FileHeader {
+            version: fileformat::VERSION,
+            compression: self.compression.codec_id(),
+            checksum: self.checksum,
+            serialization: self.serialization.format_id(),
+            type_tag: fileformat::type_tag::<T>(),
+            dictionary: compression::dictionary(&self.compression),
+            batch_tags: compression::is_adaptive(&self.compression),
+        }
+    }
+
+    /// Number of batches compressed at once.
+    pub(crate) fn compression_group(&self) -> usize {
+        if self.compression.is_none() {
+            1
+        } else {
+            self.compression_threads.max(1)
+        }
+    }
+
+    /// Number of batches to keep in memory before creating the file,
+    /// to train a compression dictionary on them.
+    pub(crate) fn dictionary_samples(&self) -> usize {
+        #[cfg(feature = "zstd")]
+        if let Some((batches, _)) = compression::dictionary_training(&self.compression) {
+            return batches.max(1);
+        }
+        1
+    }
+
+    /// Check if a file written with another configuration
+    /// can be read with this one. The checksum
+    /// is always taken from the file.
+    pub(crate) fn check_header<T>(&self, header: &FileHeader) -> Result<(), SwapVecError> {
+        let expected = self.file_header::<T>();
+        if header.compression != expected.compression
+            || header.batch_tags != expected.batch_tags
+            || header.serialization != expected.serialization
+            || header.type_tag != expected.type_tag
+        {
+            return Err(SwapVecError::InvalidFormat);
+        }
+        Ok(())
+    }
+}
+
 /// An only growing array type
 /// which swaps to disk, based on it's initial configuration.
 ///
@@ -118,17 +352,40 @@ pub struct SwapVec<T>
 where
     for<'a> T: Serialize + Deserialize<'a>,
 {
-    tempfile: Option<BatchWriter<File>>,
-    vector: VecDeque<T>,
+    // Owns the writer while running. Declared first,
+    // to finish writing before the file is removed.
+    writer_thread: Option<WriterThread>,
+    tempfile: Option<BatchWriter<FileHandle>>,
+    path: Option<SwapFilePath>,
+    // Reader and decoded batches for `get()`
+    reader: Option<BatchReader<FileHandle>>,
+    batch_cache: BatchCache<T>,
+    vector: PendingElements<T>,
     config: SwapVecConfig,
+    // Reused for decompressing batches
+    compression_buffer: Vec<u8>,
+    // Reused for compressing batches, one per thread
+    compression_buffers: Vec<Vec<u8>>,
+    compression_stats: Vec<BatchCompressionStats>,
+    // End of the batches of a file opened with `open`, where its
+    // old batch index is cut off before writing again.
+    truncate_at: Option<u64>,
 }
 
 impl<T: Serialize + for<'a> Deserialize<'a>> Default for SwapVec<T> {
     fn default() -> Self {
         Self {
+            writer_thread: None,
             tempfile: None,
-            vector: VecDeque::new(),
+            path: None,
+            reader: None,
+            batch_cache: BatchCache::new(SwapVecConfig::default().batch_cache_size),
+            vector: PendingElements::default(),
             config: SwapVecConfig::default(),
+            compression_buffer: Vec::new(),
+            compression_buffers: Vec::new(),
+            compression_stats: Vec::new(),
+            truncate_at: None,
         }
     }
 }
@@ -139,22 +396,124 @@ impl<T: Serialize + for<'a> Deserialize<'a>> Debug for SwapVec<T> {
             f,
             "SwapVec {{elements_in_ram: {}, elements_in_file: {}}}",
             self.vector.len(),
-            self.tempfile.as_ref().map(|x| x.batch_count()).unwrap_or(0) * self.config.batch_size,
+            self.file_element_count(),
         )
     }
 }
 
 impl<T> SwapVec<T>
 where
-    for<'a> T: Serialize + Deserialize<'a> + Clone,
+    for<'a> T: Serialize + Deserialize<'a>,
 {
-    /// Intialize with non-default configuration.
+    /// Initialize with non-default configuration.
     pub fn with_config(config: SwapVecConfig) -> Self {
         Self {
+            writer_thread: None,
             tempfile: None,
-            vector: VecDeque::new(),
+            path: None,
+            reader: None,
+            batch_cache: BatchCache::new(config.batch_cache_size),
+            vector: PendingElements::default(),
+            config,
+            compression_buffer: Vec::new(),
+            compression_buffers: Vec::new(),
+            compression_stats: Vec::new(),
+            truncate_at: None,
+        }
+    }
+
+    /// Reopen a file written by [`SwapVec::persist`], to continue
+    /// pushing elements. New batches are appended to the file,
+    /// which is never removed.
+    ///
+    /// A file of a process which has been killed can also be opened.
+    /// Then, all batches written completely are recovered.
+    /// Elements which have not been written to disk are lost.
+    ///
+    /// The configuration must match the one the file has been
+    /// written with, regarding compression and element type.
+    pub fn open(path: impl AsRef<Path>, mut config: SwapVecConfig) -> Result<Self, SwapVecError> {
+        let path = path.as_ref();
+        let mut file = FileHandle::new(OpenOptions::new().read(true).write(true).open(path)?);
+        let header = FileHeader::read_from(&mut file)?;
+        config.check_header::<T>(&header)?;
+        let index = fileformat::read_batch_index(&mut file, header.len(), header.checksum)?;
+        compression::set_dictionary(&mut config.compression, header.dictionary);
+        // The batch index is only dropped when writing,
+        // so opening alone leaves the file unchanged.
+        file.seek(SeekFrom::Start(index.data_end))?;
+        Ok(Self {
+            writer_thread: None,
+            tempfile: Some(BatchWriter::resume(
+                file,
+                header.checksum,
+                index.batch_infos,
+                index.data_end,
+            )),
+            path: Some(SwapFilePath::Persistent(path.to_path_buf())),
+            reader: None,
+            batch_cache: BatchCache::new(config.batch_cache_size),
+            vector: PendingElements::default(),
             config,
+            compression_buffer: Vec::new(),
+            compression_buffers: Vec::new(),
+            compression_stats: Vec::new(),
+            truncate_at: Some(index.data_end),
+        })
+    }
+
+    /// Write all elements to a file at `path`, which is kept
+    /// after dropping and can be opened again with [`SwapVec::open`]
+    /// or [`SwapVecIter::open`].
+    ///
+    /// If the vector has already been swapped to a temporary file,
+    /// the temporary file is moved, or copied if it has no name
+    /// (see [`SwapVecConfig::named_file`]).
+    /// ```rust
+    /// # let dir = tempfile::tempdir().unwrap();
+    /// # let path = dir.path().join("data.swapvec");
+    /// use swapvec::{SwapVec, SwapVecConfig, SwapVecIter};
+    /// let mut bigvec = SwapVec::default();
+    /// bigvec.consume(0..99).unwrap();
+    /// bigvec.persist(&path).unwrap();
+    ///
+    /// let iterator: SwapVecIter<i32> = SwapVecIter::open(&path, SwapVecConfig::default()).unwrap();
+    /// assert_eq!(iterator.count(), 99);
+    /// ```
+    pub fn persist(mut self, path: impl AsRef<Path>) -> Result<(), SwapVecError> {
+        while !self.vector.is_empty() {
+            self.write_batch()?;
+        }
+        self.join_writer()?;
+        if self.tempfile.is_none() {
+            self.create_file()?;
         }
+        self.truncate_index()?;
+        // Close file before moving it
+        drop(self.tempfile.take().unwrap().finish()?);
+        self.path.take().unwrap().persist(path.as_ref())?;
+        Ok(())
+    }
+
+    /// Set how the memory size of an element in bytes is estimated.
+    /// Only used if `swap_after_bytes` or `batch_bytes` is configured.
+    ///
+    /// By default, the serialized size of an element is used,
+    /// which is cheap to calculate for most types, but does not
+    /// account for allocator overhead or unused capacity.
+    /// ```rust
+    /// use swapvec::{SwapVec, SwapVecConfig};
+    /// let config = SwapVecConfig {
+    ///     swap_after_bytes: Some(64 * 1024 * 1024),
+    ///     ..SwapVecConfig::default()
+    /// };
+    /// let mut bigvec: SwapVec<String> = SwapVec::with_config(config);
+    /// bigvec.set_size_estimator(|s| std::mem::size_of::<String>() + s.capacity());
+    /// bigvec.push("Hello".to_string()).unwrap();
+    /// ```
+    pub fn set_size_estimator(&mut self, estimator: impl Fn(&T) -> usize + Send + Sync + 'static) {
+        self.vector
+            .set_size_estimator(&self.config, Box::new(estimator));
     }
 
     /// Give away an entire iterator for consumption.  
@@ -162,7 +521,7 @@ where
     pub fn consume(&mut self, it: impl Iterator<Item = T>) -> Result<(), SwapVecError> {
         for element in it {
             self.push(element)?;
-            self.after_push_work()?;
+            self.write_due_batches()?;
         }
         Ok(())
     }
@@ -175,59 +534,417 @@ where
     /// write one batch to disk, until the elements in memory have a count
     /// smaller than or equal to batch size.
     pub fn push(&mut self, element: T) -> Result<(), SwapVecError> {
-        self.vector.push_back(element);
-        self.after_push_work()
+        if self.writer_thread.as_ref().is_some_and(|t| t.has_failed()) {
+            self.join_writer()?;
+        }
+        let serialization = self.config.serialization;
+        self.vector.push(&self.config, element, |element| {
+            serialization.serialized_size(element).unwrap_or(0)
+        });
+        self.write_due_batches()
+    }
+
+    /// Get the element at `index`, reading the batch it is
+    /// contained in from disk, if it has been swapped out.
+    /// The last `batch_cache_size` batches read are kept in memory.
+    ///
+    /// Needs a mutable reference, to update the cache.
+    /// ```rust
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume(0..9).unwrap();
+    /// assert_eq!(bigvec.get(3).unwrap(), Some(&3));
+    /// assert_eq!(bigvec.get(9).unwrap(), None);
+    /// ```
+    pub fn get(&mut self, index: usize) -> Result<Option<&T>, SwapVecError> {
+        let file_elements = self.file_element_count();
+        if index >= file_elements {
+            return Ok(self.vector.elements().get(index - file_elements));
+        }
+        self.update_reader()?;
+        let reader = self.reader.as_mut().unwrap();
+        let (batch, position) = reader.locate(index).unwrap();
+        if self.batch_cache.get(batch).is_none() {
+            reader.seek_batch(batch)?;
+            let buffer = reader.read_batch()?.ok_or(SwapVecError::InvalidFormat)?;
+            let elements = decode_batch(&self.config, buffer, &mut self.compression_buffer)?;
+            self.batch_cache.insert(batch, elements);
+        }
+        Ok(self.batch_cache.get(batch).unwrap().get(position))
+    }
+
+    // Open reader for `get()` or make new batches readable.
+    fn update_reader(&mut self) -> Result<(), SwapVecError> {
+        let (batch_infos, checksum) = self.file_batch_infos()?;
+        match self.reader.as_mut() {
+            Some(reader) => reader.extend_batch_infos(&batch_infos[reader.batch_count()..]),
+            None => {
+                let file = self.path.as_ref().unwrap().open()?;
+                self.reader = Some(BatchReader::new(file, batch_infos, checksum)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Batches written to the file, waiting for
+    // the background writer if there is one.
+    fn file_batch_infos(&self) -> Result<(Vec<BatchInfo>, Checksum), SwapVecError> {
+        match self.writer_thread.as_ref() {
+            Some(thread) => thread.sync(),
+            None => {
+                let writer = self.tempfile.as_ref().unwrap();
+                Ok((writer.batch_infos().to_vec(), writer.checksum()))
+            }
+        }
+    }
+
+    fn file_element_count(&self) -> usize {
+        match self.writer_thread.as_ref() {
+            Some(thread) => thread.element_count(),
+            None => self.tempfile.as_ref().map_or(0, |w| w.element_count()),
+        }
+    }
+
+    // Wait for the background writer to write all batches,
+    // and take back the file.
+    fn join_writer(&mut self) -> Result<(), SwapVecError> {
+        let Some(thread) = self.writer_thread.take() else {
+            return Ok(());
+        };
+        let outcome = thread.join();
+        self.tempfile = Some(outcome.writer);
+        self.compression_stats.extend(outcome.stats);
+        outcome.error.map_or(Ok(()), Err)
+    }
+
+    /// Total number of elements, both in the file and in memory.
+    pub fn len(&self) -> usize {
+        self.file_element_count() + self.vector.len()
+    }
+
+    /// Returns `true` if no elements have been pushed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
     /// Check if enough items have been pushed so that
     /// the temporary file has been created.  
     /// Will be false if element count is below swap_after and below batch_size
     pub fn written_to_file(&self) -> bool {
-        self.tempfile.is_some()
+        self.tempfile.is_some() || self.writer_thread.is_some()
+    }
+
+    /// Path of the temporary file, if it has been created with
+    /// [`SwapVecConfig::named_file`] or opened with [`SwapVec::open`].
+    /// Useful for diagnostics, the file will be removed
+    /// once the vector (or its iterator) is dropped,
+    /// unless it has been opened with [`SwapVec::open`].
+    pub fn file_path(&self) -> Option<&Path> {
+        self.path.as_ref().and_then(|p| p.path())
     }
 
-    /// Get the file size in bytes of the temporary file.
-    /// Might do IO and therefore could return some Result.
+    /// Get the file size in bytes of the temporary file,
+    /// including the headers of the file format.
+    /// Batches waiting for the background writer are not included.
     pub fn file_size(&self) -> Option<usize> {
-        self.tempfile.as_ref().map(|f| f.bytes_written())
+        match self.writer_thread.as_ref() {
+            Some(thread) => Some(thread.bytes_written()),
+            None => self.tempfile.as_ref().map(|f| f.bytes_written()),
+        }
     }
 
-    /// Basically int(elements pushed / batch size)
+    /// Basically int(elements pushed / batch size),
+    /// if no byte limits are configured.
+    /// Includes batches waiting for the background writer.
     pub fn batches_written(&self) -> usize {
-        match self.tempfile.as_ref() {
-            None => 0,
-            Some(f) => f.batch_count(),
+        match (self.writer_thread.as_ref(), self.tempfile.as_ref()) {
+            (Some(thread), _) => thread.batch_count(),
+            (None, Some(f)) => f.batch_count(),
+            (None, None) => 0,
         }
     }
 
-    fn after_push_work(&mut self) -> Result<(), SwapVecError> {
-        if self.vector.len() <= self.config.batch_size {
+    /// Size before and after compression of every batch
+    /// written by this instance. Batches of a reopened
+    /// file are not included.
+    ///
+    /// Batches written by the background writer are only
+    /// included once it has been waited for, e.g. by [`SwapVec::get`].
+    pub fn compression_stats(&self) -> &[BatchCompressionStats] {
+        &self.compression_stats
+    }
+
+    fn create_file(&mut self) -> Result<(), SwapVecError> {
+        let (file, path) = self.config.create_tempfile()?;
+        let header = self.config.file_header::<T>();
+        self.tempfile = Some(BatchWriter::new(file, header)?);
+        self.path = Some(path);
+        Ok(())
+    }
+
+    fn write_serialized(&mut self, batches: Vec<SerializedBatch>) -> Result<(), SwapVecError> {
+        self.truncate_index()?;
+        if let Some(queue_size) = self.config.background_writer {
+            let thread = self.writer_thread.get_or_insert_with(|| {
+                let writer = self.tempfile.take().unwrap();
+                WriterThread::spawn(writer, self.config.compression.clone(), queue_size)
+            });
+            if !thread.send(batches) {
+                // Stopped after an error, which is returned
+                return self.join_writer();
+            }
             return Ok(());
         }
-        if self.tempfile.is_none() && self.vector.len() <= self.config.swap_after {
-            return Ok(());
+        write_compressed(
+            self.tempfile.as_mut().unwrap(),
+            &self.config.compression,
+            &batches,
+            &mut self.compression_buffers,
+            &mut self.compression_stats,
+        )
+    }
+
+    // Cut off the batch index and incomplete frames of an opened
+    // file before appending, both are written again when finishing.
+    fn truncate_index(&mut self) -> Result<(), SwapVecError> {
+        if let Some(data_end) = self.truncate_at.take() {
+            self.tempfile
+                .as_ref()
+                .unwrap()
+                .get_ref()
+                .set_len(data_end)?;
         }
+        Ok(())
+    }
+}
 
-        // Flush batch
-        if self.tempfile.is_none() {
-            let tf = tempfile::Builder::new().tempfile_in(".")?.into_file();
-            self.tempfile = Some(BatchWriter::new(tf));
+impl<T> WriteBatches<T> for SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    fn pending(&self) -> &PendingElements<T> {
+        &self.vector
+    }
+
+    fn config(&self) -> &SwapVecConfig {
+        &self.config
+    }
+
+    fn has_file(&self) -> bool {
+        self.written_to_file()
+    }
+
+    fn write_batch(&mut self) -> Result<(), SwapVecError> {
+        let written_to_file = self.written_to_file();
+        let serialization = self.config.serialization;
+        let batches = self
+            .vector
+            .take_batches(&mut self.config, written_to_file, |batch| {
+                Ok(serialization.serialize(&batch)?)
+            })?;
+        if !written_to_file {
+            self.create_file()?;
         }
-        assert!(self.tempfile.is_some());
-        let batch: Vec<_> = self.vector.drain(0..self.config.batch_size).collect();
+        self.write_serialized(batches)
+    }
+}
 
-        let buffer = bincode::serialize(&batch)?;
-        let compressed = self.config.compression.compress(buffer);
-        self.tempfile.as_mut().unwrap().write_batch(&compressed)?;
-        Ok(())
+impl<T> SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a> + Clone,
+{
+    /// Iterate over all elements without consuming the vector,
+    /// which can be pushed to afterwards.
+    ///
+    /// Swapped out elements are read with a separate handle
+    /// to the temporary file, elements in memory are cloned.
+    /// ```rust
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume(0..9).unwrap();
+    /// let sum: i32 = bigvec.iter().map(|v| v.unwrap()).sum();
+    /// bigvec.push(sum).unwrap();
+    /// ```
+    pub fn iter(&self) -> SwapVecRefIter<'_, T> {
+        let reader = self.written_to_file().then(|| {
+            let (batch_infos, checksum) = self.file_batch_infos()?;
+            let file = self.path.as_ref().unwrap().open()?;
+            Ok(BatchReader::new(file, batch_infos, checksum)?)
+        });
+        SwapVecRefIter::new(reader, self.vector.elements(), &self.config)
+    }
+
+    /// Same as [`IntoIterator::into_iter`], but elements in memory
+    /// are cloned when read instead of moved out, so they can be
+    /// read again after [`SwapVecIter::reset`].
+    // Takes precedence over the trait method, if `T: Clone`
+    #[allow(clippy::should_implement_trait)]
+    pub fn into_iter(self) -> SwapVecIter<T> {
+        IntoIterator::into_iter(self).keep_last_elements(T::clone)
     }
 }
 
-impl<T: Serialize + for<'a> Deserialize<'a> + Clone> IntoIterator for SwapVec<T> {
+#[cfg(feature = "rayon")]
+impl<T> SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a> + Clone + Send + Sync,
+{
+    /// Iterate over all elements in parallel with rayon,
+    /// without consuming the vector.
+    ///
+    /// Batches of the temporary file are handed to the workers
+    /// in disjoint ranges, each worker reading with its own handle.
+    /// Useful if processing the elements is heavier than reading them.
+    /// ```rust
+    /// use rayon::iter::ParallelIterator;
+    ///
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume(0..99u64).unwrap();
+    /// let sum: u64 = bigvec.par_iter().map(|v| v.unwrap()).sum();
+    /// assert_eq!(sum, 4851);
+    /// ```
+    pub fn par_iter(&self) -> SwapVecParIter<'_, T> {
+        let file = self.written_to_file().then(|| {
+            let (batch_infos, checksum) = self.file_batch_infos()?;
+            Ok(FileBatches {
+                file: self.path.as_ref().unwrap(),
+                batch_infos,
+                checksum,
+            })
+        });
+        SwapVecParIter::new(file, self.vector.elements(), &self.config)
+    }
+}
+
+impl<T> SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a> + Send + 'static,
+{
+    /// Move the vector to a thread, which pushes the elements
+    /// sent by many producers. The returned sender can be cloned,
+    /// and sending blocks while `batch_size` elements are queued.
+    ///
+    /// Once all senders are dropped,
+    /// [`SwapVecCollector::finish`] returns the iterator.
+    /// ```rust
+    /// let (sender, collector) = swapvec::SwapVec::default().sender();
+    /// let producers: Vec<_> = (0..4)
+    ///     .map(|p| {
+    ///         let sender = sender.clone();
+    ///         std::thread::spawn(move || {
+    ///             for i in 0..100 {
+    ///                 sender.send(p * 100 + i).unwrap();
+    ///             }
+    ///         })
+    ///     })
+    ///     .collect();
+    /// drop(sender);
+    /// producers.into_iter().for_each(|p| p.join().unwrap());
+    /// assert_eq!(collector.finish().unwrap().count(), 400);
+    /// ```
+    pub fn sender(self) -> (SwapVecSender<T>, SwapVecCollector<T>) {
+        let capacity = self.config.batch_size;
+        SwapVecCollector::spawn(self, capacity)
+    }
+}
+
+impl<T> SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a>,
+{
+    /// Sort the elements with `compare`, without keeping
+    /// all of them in memory, and iterate over them.
+    ///
+    /// External merge sort: runs of up to `swap_after` elements,
+    /// or `swap_after_bytes` as estimated for pushing if configured,
+    /// are sorted in memory and written to a new temporary file,
+    /// except for the last one. Up to `merge_fan_in` runs are
+    /// then merged while iterating, more runs are merged into
+    /// longer ones before. Without a temporary file, the elements
+    /// are simply sorted in memory.
+    ///
+    /// The file of the vector is only removed once all runs are
+    /// written, so up to twice its size is needed on disk.
+    /// [`SortedSwapVec`](crate::SortedSwapVec) sorts the runs
+    /// while pushing instead, without writing the elements twice.
+    ///
+    /// The sort is stable. Errors are returned by the iterator.
+    /// ```rust
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume([3, 1, 2].into_iter()).unwrap();
+    /// let sorted: Vec<i32> = bigvec.sort_by(|a, b| b.cmp(a)).map(|v| v.unwrap()).collect();
+    /// assert_eq!(sorted, vec![3, 2, 1]);
+    /// ```
+    pub fn sort_by<F>(self, compare: F) -> SwapVecSortedIter<T, F>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.sort_with(compare, true)
+    }
+
+    /// Like [`SwapVec::sort_by`], but the runs are sorted
+    /// unstable, so equal elements might be reordered.
+    pub fn sort_unstable_by<F>(self, compare: F) -> SwapVecSortedIter<T, F>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.sort_with(compare, false)
+    }
+
+    /// Like [`SwapVec::sort_by`], comparing the keys
+    /// extracted by `f`. Keys are extracted for every comparison.
+    pub fn sort_by_key<K, F>(self, mut f: F) -> SwapVecSortedIter<T, impl FnMut(&T, &T) -> Ordering>
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        self.sort_by(move |a, b| f(a).cmp(&f(b)))
+    }
+
+    fn sort_with<F>(mut self, compare: F, stable: bool) -> SwapVecSortedIter<T, F>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let config = self.config.clone();
+        let size_estimator = self.vector.take_size_estimator();
+        sort::sort(config, size_estimator, self.into_iter(), compare, stable)
+    }
+}
+
+impl<T> SwapVec<T>
+where
+    for<'a> T: Serialize + Deserialize<'a> + Ord,
+{
+    /// Iterate over the elements in ascending order,
+    /// see [`SwapVec::sort_by`].
+    /// ```rust
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume((0..99).rev()).unwrap();
+    /// let mut sorted = bigvec.into_sorted_iter();
+    /// assert_eq!(sorted.next().unwrap().unwrap(), 0);
+    /// ```
+    pub fn into_sorted_iter(self) -> SwapVecSortedIter<T, fn(&T, &T) -> Ordering> {
+        self.sort_by(T::cmp)
+    }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> IntoIterator for SwapVec<T> {
     type Item = Result<T, SwapVecError>;
     type IntoIter = SwapVecIter<T>;
 
-    fn into_iter(self) -> Self::IntoIter {
-        SwapVecIter::new(self.tempfile, self.vector, self.config)
+    fn into_iter(mut self) -> Self::IntoIter {
+        // The iterator moves elements in memory out, so write them
+        // to the file if existing, to be read again after a reset.
+        let mut write_error = None;
+        while self.written_to_file() && !self.vector.is_empty() && write_error.is_none() {
+            write_error = self.write_batch().err();
+        }
+        let writer_error = self.join_writer().err().or(write_error);
+        let writer_error = writer_error.or_else(|| self.truncate_index().err());
+        SwapVecIter::new(
+            writer_error,
+            self.tempfile,
+            self.path,
+            self.vector.into_elements(),
+            self.config,
+        )
     }
 }
@@ -1,32 +1,32 @@
-use std::collections::VecDeque;
+use std::collections::{vec_deque, VecDeque};
 use std::fs::File;
+use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
-use crate::checkedfile::{BatchReader, BatchWriter};
-use crate::compression::Compress;
+use crate::checkedfile::{BatchReader, BatchWriter, FileHandle, SwapFilePath};
+use crate::compression::{self, Compress};
 use crate::error::SwapVecError;
+use crate::prefetch::Prefetcher;
+use crate::serialization::Serializer;
 use crate::swapvec::SwapVecConfig;
 
-struct VecDequeIndex<T: Clone> {
-    value: VecDeque<T>,
-}
-
-impl<T: Clone> From<VecDeque<T>> for VecDequeIndex<T> {
-    fn from(value: VecDeque<T>) -> Self {
-        Self { value }
-    }
-}
-
-impl<T: Clone> VecDequeIndex<T> {
-    fn get(&self, i: usize) -> Option<T> {
-        let (a, b) = self.value.as_slices();
-        if i < a.len() {
-            a.get(i).cloned()
-        } else {
-            b.get(i - a.len()).cloned()
-        }
+/// Decompress and deserialize a batch read from disk.
+/// `decompressed` is a buffer to reuse across batches.
+pub(crate) fn decode_batch<T: for<'a> Deserialize<'a>>(
+    config: &SwapVecConfig,
+    buffer: &[u8],
+    decompressed: &mut Vec<u8>,
+) -> Result<Vec<T>, SwapVecError> {
+    if config.compression.is_none() {
+        return Ok(config.serialization.deserialize(buffer)?);
     }
+    decompressed.clear();
+    config
+        .compression
+        .decompress(buffer, decompressed)
+        .map_err(SwapVecError::Decompression)?;
+    Ok(config.serialization.deserialize(decompressed)?)
 }
 
 /// Iterator for SwapVec.
@@ -35,92 +35,265 @@ impl<T: Clone> VecDequeIndex<T> {
 /// so every item is wrapped in a `Result`.  
 /// The iterator aborts after the first error.
 ///
+/// Can be iterated from both ends, see [`DoubleEndedIterator`].
+///
 /// Dropping the iterator removes the temporary file, if existing.  
 /// Also quitting the program should remove the temporary file.
 pub struct SwapVecIter<T>
 where
-    for<'a> T: Serialize + Deserialize<'a> + Clone,
+    for<'a> T: Serialize + Deserialize<'a>,
 {
     // Do not error on new, because into_iter()
     // is not allowed to fail. Fail at first try then.
-    new_error: Option<std::io::Error>,
-    current_batch_rev: Vec<T>,
-    tempfile: Option<BatchReader<File>>,
+    new_error: Option<SwapVecError>,
+    // Reads ahead of front_batch_index, if configured.
+    // Declared before path, to stop before the file is removed.
+    prefetcher: Option<Prefetcher>,
+    tempfile: Option<BatchReader<FileHandle>>,
+    path: Option<SwapFilePath>,
+    // Remaining elements of the batches read
+    // from the front and from the back.
+    front_batch: VecDeque<T>,
+    back_batch: VecDeque<T>,
+    // Batches not read yet are front_batch_index..back_batch_index
+    front_batch_index: usize,
+    back_batch_index: usize,
     // last_elements are elements,
     // which have not been written to disk.
-    // Therefore, for iterating from zero,
-    // first read elements from disk and
-    // then from last_elements.
-    last_elements: VecDequeIndex<T>,
-    last_elements_index: usize,
+    // They are treated as one more batch after the file batches,
+    // which is cloned when read if possible, otherwise moved out,
+    // so it can only be read once.
+    last_elements: VecDeque<T>,
+    last_elements_count: usize,
+    clone_element: Option<fn(&T) -> T>,
+    // Reused for decompressing batches
+    decompressed: Vec<u8>,
     config: SwapVecConfig,
 }
 
-impl<T: Serialize + for<'a> Deserialize<'a> + Clone> SwapVecIter<T> {
+impl<T: Serialize + for<'a> Deserialize<'a>> SwapVecIter<T> {
     pub(crate) fn new(
-        tempfile_written: Option<BatchWriter<File>>,
+        writer_error: Option<SwapVecError>,
+        tempfile_written: Option<BatchWriter<FileHandle>>,
+        path: Option<SwapFilePath>,
         last_elements: VecDeque<T>,
         config: SwapVecConfig,
     ) -> Self {
         let (tempfile, new_error) = match tempfile_written.map(|v| v.try_into()) {
-            None => (None, None),
-            Some(Ok(v)) => (Some(v), None),
-            Some(Err(e)) => (None, Some(e)),
+            None => (None, writer_error),
+            Some(Ok(v)) => (Some(v), writer_error),
+            Some(Err(e)) => (None, Some(SwapVecError::from(e))),
         };
+        Self::from_parts(new_error, tempfile, path, last_elements, config)
+    }
 
-        let last_elements: VecDequeIndex<_> = last_elements.into();
-        Self {
+    fn from_parts(
+        new_error: Option<SwapVecError>,
+        tempfile: Option<BatchReader<FileHandle>>,
+        path: Option<SwapFilePath>,
+        last_elements: VecDeque<T>,
+        config: SwapVecConfig,
+    ) -> Self {
+        let last_elements_count = last_elements.len();
+        let mut iterator = Self {
             new_error,
-            current_batch_rev: Vec::with_capacity(config.batch_size),
-            last_elements,
-            last_elements_index: 0,
+            prefetcher: None,
             tempfile,
+            path,
+            front_batch: VecDeque::new(),
+            back_batch: VecDeque::new(),
+            front_batch_index: 0,
+            back_batch_index: 0,
+            last_elements,
+            last_elements_count,
+            clone_element: None,
+            decompressed: Vec::new(),
             config,
-        }
+        };
+        iterator.reset_positions();
+        iterator
     }
 
-    fn read_batch(&mut self) -> Result<Option<Vec<T>>, SwapVecError> {
-        if self.tempfile.is_none() {
-            return Ok(None);
+    // Clone elements in memory when reading them,
+    // so they can be read again after a reset.
+    pub(crate) fn keep_last_elements(mut self, clone_element: fn(&T) -> T) -> Self {
+        self.clone_element = Some(clone_element);
+        self
+    }
+
+    /// Iterate over a file written by [`SwapVec::persist`](crate::SwapVec::persist).
+    /// The file is not removed when dropping the iterator.
+    ///
+    /// The configuration must match the one the file has been
+    /// written with, regarding compression and element type.
+    pub fn open(path: impl AsRef<Path>, mut config: SwapVecConfig) -> Result<Self, SwapVecError> {
+        let path = path.as_ref();
+        let (header, _, reader) = BatchReader::open(FileHandle::new(File::open(path)?))?;
+        config.check_header::<T>(&header)?;
+        compression::set_dictionary(&mut config.compression, header.dictionary);
+        Ok(Self::from_parts(
+            None,
+            Some(reader),
+            Some(SwapFilePath::Persistent(path.to_path_buf())),
+            VecDeque::new(),
+            config,
+        ))
+    }
+
+    /// Path of the temporary file, if the vector has been swapped
+    /// to disk and the file has a name, see [`SwapVec::file_path`](crate::SwapVec::file_path).
+    pub fn file_path(&self) -> Option<&Path> {
+        self.path.as_ref().and_then(|p| p.path())
+    }
+
+    /// Number of elements left in the iteration.
+    /// A read error ends the iteration before all of them are returned.
+    pub fn len(&self) -> usize {
+        self.front_batch.len()
+            + (self.batch_start(self.back_batch_index) - self.batch_start(self.front_batch_index))
+            + self.back_batch.len()
+    }
+
+    /// Returns `true` if no elements are left.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn file_batch_count(&self) -> usize {
+        self.tempfile.as_ref().map_or(0, |t| t.batch_count())
+    }
+
+    fn batch_count(&self) -> usize {
+        self.file_batch_count() + usize::from(self.last_elements_count > 0)
+    }
+
+    // Index of the first element of a batch,
+    // including the batch of last elements.
+    fn batch_start(&self, batch: usize) -> usize {
+        let file_batches = self.file_batch_count();
+        let start = self
+            .tempfile
+            .as_ref()
+            .map_or(0, |t| t.batch_start(batch.min(file_batches)));
+        if batch > file_batches {
+            start + self.last_elements_count
+        } else {
+            start
         }
-        assert!(self.tempfile.is_some());
+    }
+
+    fn reset_positions(&mut self) {
+        self.prefetcher = None;
+        self.front_batch.clear();
+        self.back_batch.clear();
+        self.front_batch_index = 0;
+        self.back_batch_index = self.batch_count();
+    }
+
+    fn read_batch(&mut self, batch: usize) -> Result<VecDeque<T>, SwapVecError> {
         if let Some(err) = self.new_error.take() {
-            return Err(err.into());
+            return Err(err);
+        }
+        if batch == self.file_batch_count() {
+            if let Some(clone_element) = self.clone_element {
+                return Ok(self.last_elements.iter().map(clone_element).collect());
+            }
+            if self.last_elements.is_empty() {
+                return Err(SwapVecError::AlreadyConsumed);
+            }
+            return Ok(std::mem::take(&mut self.last_elements));
         }
-
         let tempfile = self.tempfile.as_mut().unwrap();
-        let buffer = tempfile.read_batch()?;
-        if buffer.is_none() {
-            return Ok(None);
+        if tempfile.batch_index() != batch {
+            tempfile.seek_batch(batch)?;
         }
-        let buffer = buffer.unwrap();
-        let decompressed: Vec<u8> = self
-            .config
-            .compression
-            .decompress(buffer.to_vec())
-            .map_err(|_| SwapVecError::Decompression)?;
+        let buffer = tempfile.read_batch()?.ok_or(SwapVecError::InvalidFormat)?;
+        let batch: Vec<T> = decode_batch(&self.config, buffer, &mut self.decompressed)?;
+        Ok(batch.into())
+    }
 
-        let batch: Vec<T> = bincode::deserialize(&decompressed)?;
+    // Read the next batch from the front, taking it from
+    // the prefetcher, which is started here if needed.
+    fn read_front_batch(&mut self, batch: usize) -> Result<VecDeque<T>, SwapVecError> {
+        let Some(batches) = self.config.prefetch else {
+            return self.read_batch(batch);
+        };
+        if batch >= self.file_batch_count() || self.new_error.is_some() {
+            return self.read_batch(batch);
+        }
+        if self.prefetcher.as_ref().map(|p| p.next_batch()) != Some(batch) {
+            let tempfile = self.tempfile.as_ref().unwrap();
+            // Batches from the back are read by the iterator itself
+            let end = self.back_batch_index.min(tempfile.batch_count());
+            self.prefetcher = Some(Prefetcher::spawn(
+                self.path.as_ref().unwrap().open()?,
+                tempfile.batch_infos()[..end].to_vec(),
+                tempfile.checksum(),
+                self.config.compression.clone(),
+                batch,
+                batches,
+            ));
+        }
+        let decompressed = self.prefetcher.as_mut().unwrap().recv()?;
+        let batch: Vec<T> = self.config.serialization.deserialize(&decompressed)?;
+        Ok(batch.into())
+    }
 
-        Ok(Some(batch))
+    // Abort iteration after an error
+    fn abort(&mut self, err: SwapVecError) -> Option<Result<T, SwapVecError>> {
+        self.front_batch.clear();
+        self.back_batch.clear();
+        self.front_batch_index = self.back_batch_index;
+        Some(Err(err))
     }
 
-    fn next_in_batch(&mut self) -> Result<Option<T>, SwapVecError> {
-        if let Some(v) = self.current_batch_rev.pop() {
-            return Ok(Some(v));
+    /// Continue the iteration at the element with the given index.
+    /// Only the batch containing the element is read from disk.
+    /// Seeking past the last element ends the iteration.
+    ///
+    /// Seeking to an element kept in memory fails with
+    /// [`SwapVecError::AlreadyConsumed`], if it has been
+    /// moved out already, see [`SwapVecIter::reset`].
+    ///
+    /// Iterating from the back starts at the last element again.
+    /// ```rust
+    /// let mut bigvec = swapvec::SwapVec::default();
+    /// bigvec.consume(0..99).unwrap();
+    /// let mut iterator = bigvec.into_iter();
+    /// iterator.seek(90).unwrap();
+    /// assert_eq!(iterator.next().unwrap().unwrap(), 90);
+    /// ```
+    pub fn seek(&mut self, index: usize) -> Result<(), SwapVecError> {
+        if let Some(err) = self.new_error.take() {
+            return Err(err);
         }
-        if let Some(mut new_batch) = self.read_batch()? {
-            new_batch.reverse();
-            self.current_batch_rev = new_batch;
-            Ok(self.current_batch_rev.pop())
-        } else {
-            Ok(None)
+        self.reset_positions();
+        let batch_count = self.batch_count();
+        if index >= self.batch_start(batch_count) {
+            self.front_batch_index = batch_count;
+            return Ok(());
         }
+        let file_elements = self.batch_start(self.file_batch_count());
+        let (batch, position) = match self.tempfile.as_ref().and_then(|t| t.locate(index)) {
+            Some(location) => location,
+            None => (self.file_batch_count(), index - file_elements),
+        };
+        let mut elements = self.read_front_batch(batch)?;
+        elements.drain(..position);
+        self.front_batch = elements;
+        self.front_batch_index = batch + 1;
+        Ok(())
     }
 
     /// Resets the iteration, starting from the first element.
     /// If a file exists, it will be read from the beginning.  
     ///
+    /// Elements of a vector never swapped to disk are not serialized.
+    /// If `T: Clone`, [`SwapVec::into_iter`](crate::SwapVec::into_iter)
+    /// clones them when read, so they can be read again.
+    /// Otherwise they are moved out, and iterating them
+    /// again returns [`SwapVecError::AlreadyConsumed`].
+    ///
     /// To use this feature, you probably don't want to consume
     /// the iterator (`bigvec.map(|x| x * 2)`), but to use
     /// [`Iterator::by_ref()`](https://doc.rust-lang.org/std/iter/trait.Iterator.html#method.by_ref)
@@ -131,33 +304,141 @@ impl<T: Serialize + for<'a> Deserialize<'a> + Clone> SwapVecIter<T> {
     /// let sum: usize = new_iterator.by_ref().map(|v| v.unwrap()).sum();
     /// new_iterator.reset();
     /// let sum_double: usize = new_iterator.by_ref().map(|v| v.unwrap() * 2).sum();
+    /// assert_eq!(sum_double, sum * 2);
     /// ```
     pub fn reset(&mut self) {
-        self.current_batch_rev.clear();
-        self.last_elements_index = 0;
+        self.reset_positions();
         if let Some(tempfile) = self.tempfile.as_mut() {
             if let Err(e) = tempfile.reset() {
-                self.new_error = Some(e);
+                self.new_error = Some(e.into());
             }
         }
     }
 }
 
-impl<T: Serialize + for<'a> Deserialize<'a> + Clone> Iterator for SwapVecIter<T> {
+impl<T: Serialize + for<'a> Deserialize<'a>> Iterator for SwapVecIter<T> {
     type Item = Result<T, SwapVecError>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(item) = self.current_batch_rev.pop() {
-            return Some(Ok(item));
+        if let Some(err) = self.new_error.take() {
+            return self.abort(err);
+        }
+        loop {
+            if let Some(item) = self.front_batch.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.front_batch_index >= self.back_batch_index {
+                break;
+            }
+            match self.read_front_batch(self.front_batch_index) {
+                Err(err) => return self.abort(err),
+                Ok(batch) => self.front_batch = batch,
+            }
+            self.front_batch_index += 1;
         }
+        self.back_batch.pop_front().map(Ok)
+    }
 
-        match self.next_in_batch() {
-            Err(err) => Some(Err(err)),
-            Ok(Some(item)) => Some(Ok(item)),
-            Ok(None) => {
-                let index = self.last_elements_index;
-                self.last_elements_index += 1;
-                self.last_elements.get(index).map(|x| Ok(x))
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> ExactSizeIterator for SwapVecIter<T> {}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> DoubleEndedIterator for SwapVecIter<T> {
+    /// Elements in memory are returned first, then
+    /// batches are read from disk, starting with the last one.
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.new_error.take() {
+            return self.abort(err);
+        }
+        loop {
+            if let Some(item) = self.back_batch.pop_back() {
+                return Some(Ok(item));
+            }
+            if self.back_batch_index <= self.front_batch_index {
+                break;
+            }
+            match self.read_batch(self.back_batch_index - 1) {
+                Err(err) => return self.abort(err),
+                Ok(batch) => self.back_batch = batch,
+            }
+            self.back_batch_index -= 1;
+        }
+        self.front_batch.pop_back().map(Ok)
+    }
+}
+
+/// Borrowing iterator for SwapVec, see [`SwapVec::iter`](crate::SwapVec::iter).
+///
+/// Batches are read with a separate handle to the temporary file,
+/// elements kept in memory are cloned.
+/// The iterator stops after the first error.
+pub struct SwapVecRefIter<'a, T>
+where
+    for<'de> T: Serialize + Deserialize<'de> + Clone,
+{
+    reader: Option<Result<BatchReader<FileHandle>, SwapVecError>>,
+    current_batch: std::vec::IntoIter<T>,
+    last_elements: vec_deque::Iter<'a, T>,
+    decompressed: Vec<u8>,
+    config: &'a SwapVecConfig,
+}
+
+impl<'a, T: Serialize + for<'de> Deserialize<'de> + Clone> SwapVecRefIter<'a, T> {
+    pub(crate) fn new(
+        reader: Option<Result<BatchReader<FileHandle>, SwapVecError>>,
+        last_elements: &'a VecDeque<T>,
+        config: &'a SwapVecConfig,
+    ) -> Self {
+        Self {
+            reader,
+            current_batch: Vec::new().into_iter(),
+            last_elements: last_elements.iter(),
+            decompressed: Vec::new(),
+            config,
+        }
+    }
+
+    fn read_batch(&mut self) -> Result<Option<Vec<T>>, SwapVecError> {
+        let reader = match self.reader.as_mut() {
+            None => return Ok(None),
+            Some(Err(_)) => return Err(self.reader.take().unwrap().err().unwrap()),
+            Some(Ok(reader)) => reader,
+        };
+        match reader.read_batch()? {
+            Some(buffer) => Ok(Some(decode_batch(
+                self.config,
+                buffer,
+                &mut self.decompressed,
+            )?)),
+            None => {
+                self.reader = None;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<'a, T: Serialize + for<'de> Deserialize<'de> + Clone> Iterator for SwapVecRefIter<'a, T> {
+    type Item = Result<T, SwapVecError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.current_batch.next() {
+                return Some(Ok(item));
+            }
+            match self.read_batch() {
+                Err(err) => {
+                    // Abort iteration
+                    self.reader = None;
+                    self.last_elements = vec_deque::Iter::default();
+                    return Some(Err(err));
+                }
+                Ok(Some(batch)) => self.current_batch = batch.into_iter(),
+                Ok(None) => return self.last_elements.next().cloned().map(Ok),
             }
         }
     }
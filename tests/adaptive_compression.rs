@@ -0,0 +1,81 @@
+#![cfg(feature = "lz4")]
+
+use swapvec::{Compression, SwapVec, SwapVecConfig, SwapVecError, SwapVecIter};
+
+fn config(compression: Compression) -> SwapVecConfig {
+    SwapVecConfig {
+        compression: Some(compression),
+        swap_after: 4,
+        batch_size: 4,
+        ..SwapVecConfig::default()
+    }
+}
+
+fn adaptive() -> Compression {
+    Compression::Adaptive {
+        compression: Box::new(Compression::Lz4),
+        max_ratio: 0.9,
+    }
+}
+
+// Alternating batches of repetitive and incompressible blobs
+fn blobs() -> Vec<Vec<u8>> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..40)
+        .map(|i| {
+            if (i / 4) % 2 == 0 {
+                vec![i as u8; 1000]
+            } else {
+                (0..1000)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        state as u8
+                    })
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn store_incompressible_batches_raw() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("adaptive.swapvec");
+
+    let mut v = SwapVec::with_config(config(adaptive()));
+    v.consume(blobs().into_iter()).unwrap();
+    let stats = v.compression_stats().to_vec();
+    assert_eq!(stats.len(), v.batches_written());
+    assert!(stats.iter().any(|s| s.compressed && s.ratio() < 0.1));
+    assert!(stats.iter().any(|s| !s.compressed));
+    for s in stats.iter().filter(|s| !s.compressed) {
+        // One byte tag in front of the raw batch
+        assert_eq!(s.stored_bytes, s.uncompressed_bytes + 1);
+    }
+    v.persist(&path).unwrap();
+
+    let read_back: Vec<Vec<u8>> = SwapVecIter::open(&path, config(adaptive()))
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, blobs());
+
+    // Batches are tagged, so the file cannot be read without adaptive mode
+    assert!(matches!(
+        SwapVecIter::<Vec<u8>>::open(&path, config(Compression::Lz4)),
+        Err(SwapVecError::InvalidFormat)
+    ));
+}
+
+#[test]
+fn stats_without_adaptive_mode() {
+    let mut v = SwapVec::with_config(config(Compression::Lz4));
+    v.consume(blobs().into_iter()).unwrap();
+    let stats = v.compression_stats();
+    assert!(stats.iter().all(|s| s.compressed));
+    // LZ4 grows incompressible batches
+    assert!(stats.iter().any(|s| s.ratio() > 1.0));
+    assert_eq!(stats.iter().map(|s| s.elements).sum::<usize>(), 36);
+}
@@ -0,0 +1,145 @@
+#![cfg(feature = "rkyv")]
+
+use swapvec::{ArchivedSwapVec, SwapVecConfig};
+
+#[derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize, Debug, PartialEq)]
+struct Record {
+    id: u64,
+    values: [u32; 4],
+    name: String,
+}
+
+fn record(id: u64) -> Record {
+    Record {
+        id,
+        values: [id as u32; 4],
+        name: format!("record {}", id),
+    }
+}
+
+fn read_back(config: SwapVecConfig) {
+    let mut v = ArchivedSwapVec::with_config(config);
+    v.consume((0..999).map(record)).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 999);
+
+    let mut batches = v.into_batches();
+    assert_eq!(batches.element_count(), 999);
+    let mut expected = 0;
+    while let Some(batch) = batches.next_batch() {
+        for archived in batch.unwrap() {
+            assert_eq!(archived.id, expected);
+            assert_eq!(archived.values[3], expected as u32);
+            assert_eq!(archived.name, format!("record {}", expected));
+            expected += 1;
+        }
+    }
+    assert_eq!(expected, 999);
+
+    batches.reset();
+    let mut deserialized = Vec::new();
+    batches
+        .for_each(|archived| {
+            deserialized.push(rkyv::deserialize::<Record, rkyv::rancor::Error>(archived).unwrap())
+        })
+        .unwrap();
+    let expected: Vec<Record> = (0..999).map(record).collect();
+    assert_eq!(deserialized, expected);
+}
+
+#[test]
+fn archived() {
+    read_back(SwapVecConfig {
+        swap_after: 16,
+        batch_size: 8,
+        ..SwapVecConfig::default()
+    });
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn archived_compressed() {
+    read_back(SwapVecConfig {
+        swap_after: 16,
+        batch_size: 8,
+        compression: Some(swapvec::Compression::Lz4),
+        ..SwapVecConfig::default()
+    });
+}
+
+#[test]
+fn archived_in_memory() {
+    let mut v = ArchivedSwapVec::default();
+    v.consume(0..10u16).unwrap();
+    assert!(!v.written_to_file());
+    let mut batches = v.into_batches();
+    assert_eq!(batches.batch_count(), 1);
+    let mut sum = 0;
+    batches.for_each(|value| sum += value.to_native()).unwrap();
+    assert_eq!(sum, 45);
+}
+
+#[test]
+fn archived_byte_limits() {
+    let mut v = ArchivedSwapVec::with_config(SwapVecConfig {
+        swap_after_bytes: Some(1024),
+        batch_bytes: Some(256),
+        ..SwapVecConfig::default()
+    });
+    v.set_size_estimator(|r: &Record| std::mem::size_of::<Record>() + r.name.capacity());
+    v.consume((0..999).map(record)).unwrap();
+    assert!(v.written_to_file());
+    assert!(v.batches_written() > 999 / 8);
+    read_back_count(v, 999);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn archived_parallel_compression() {
+    let mut v = ArchivedSwapVec::with_config(SwapVecConfig {
+        swap_after: 16,
+        batch_size: 8,
+        compression: Some(swapvec::Compression::Lz4),
+        compression_threads: 4,
+        ..SwapVecConfig::default()
+    });
+    v.consume((0..999).map(record)).unwrap();
+    let stats = v.compression_stats();
+    assert_eq!(stats.len(), v.batches_written());
+    assert!(stats.iter().all(|s| s.compressed && s.elements == 8));
+    read_back_count(v, 999);
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn archived_trained_dictionary() {
+    let mut v = ArchivedSwapVec::with_config(SwapVecConfig {
+        swap_after: 16,
+        batch_size: 8,
+        compression: Some(swapvec::Compression::Zstd {
+            level: swapvec::CompressionLevel::Default,
+            dictionary: swapvec::ZstdDictionary::Train {
+                batches: 32,
+                max_size: 4096,
+            },
+        }),
+        ..SwapVecConfig::default()
+    });
+    v.consume((0..200).map(record)).unwrap();
+    // Batches are kept in memory until there are enough to train
+    assert!(!v.written_to_file());
+    v.consume((200..999).map(record)).unwrap();
+    assert!(v.written_to_file());
+    read_back_count(v, 999);
+}
+
+fn read_back_count(v: ArchivedSwapVec<Record>, count: u64) {
+    let mut expected = 0;
+    v.into_batches()
+        .for_each(|archived| {
+            assert_eq!(archived.id, expected);
+            expected += 1;
+        })
+        .unwrap();
+    assert_eq!(expected, count);
+}
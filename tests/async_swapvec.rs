@@ -0,0 +1,114 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use futures_util::{stream, Stream, StreamExt};
+use swapvec::{AsyncSwapVec, SwapVecConfig};
+
+fn config() -> SwapVecConfig {
+    SwapVecConfig {
+        #[cfg(feature = "lz4")]
+        compression: Some(swapvec::Compression::Lz4),
+        ..common::config(8)
+    }
+}
+
+#[tokio::test]
+async fn push_and_stream() {
+    let mut v = AsyncSwapVec::with_config(config());
+    for i in 0..999u64 {
+        v.push(i).await.unwrap();
+    }
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 999);
+
+    let stream = v.into_stream().await;
+    assert_eq!(stream.size_hint(), (999, Some(999)));
+    let read_back: Vec<u64> = stream.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn consume_stream() {
+    let mut v = AsyncSwapVec::with_config(config());
+    v.consume_stream(stream::iter(0..999u64)).await.unwrap();
+    v.consume(999..1000u64).await.unwrap();
+    assert_eq!(v.batches_written(), 124);
+    let read_back: Vec<u64> = v.into_stream().await.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back, (0..1000).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn in_memory() {
+    let mut v = AsyncSwapVec::default();
+    v.consume(0..10u64).await.unwrap();
+    assert!(!v.written_to_file());
+    let read_back: Vec<u64> = v.into_stream().await.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back, (0..10).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn drop_while_reading() {
+    let mut v = AsyncSwapVec::with_config(config());
+    v.consume(0..999u64).await.unwrap();
+    let mut stream = v.into_stream().await;
+    for i in 0..10 {
+        assert_eq!(stream.next().await.unwrap().unwrap(), i);
+    }
+    assert_eq!(stream.size_hint(), (989, Some(989)));
+}
+
+#[tokio::test]
+async fn byte_limits() {
+    let mut v = AsyncSwapVec::with_config(SwapVecConfig {
+        swap_after_bytes: Some(1024),
+        batch_bytes: Some(256),
+        ..SwapVecConfig::default()
+    });
+    v.set_size_estimator(|s: &String| std::mem::size_of::<String>() + s.capacity());
+    v.consume((0..999).map(|i| format!("element {}", i)))
+        .await
+        .unwrap();
+    assert!(v.written_to_file());
+    assert!(v.batches_written() > 0);
+    let read_back: Vec<String> = v.into_stream().await.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back.len(), 999);
+    assert_eq!(read_back[998], "element 998");
+}
+
+#[cfg(feature = "lz4")]
+#[tokio::test]
+async fn parallel_compression() {
+    let mut v = AsyncSwapVec::with_config(SwapVecConfig {
+        compression_threads: 4,
+        ..config()
+    });
+    v.consume(0..999u64).await.unwrap();
+    let stats = v.compression_stats();
+    assert_eq!(stats.len(), v.batches_written());
+    assert!(stats.iter().all(|s| s.compressed && s.elements == 8));
+    let read_back: Vec<u64> = v.into_stream().await.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
+
+#[cfg(feature = "zstd")]
+#[tokio::test]
+async fn trained_dictionary() {
+    let mut v = AsyncSwapVec::with_config(SwapVecConfig {
+        compression: Some(swapvec::Compression::Zstd {
+            level: swapvec::CompressionLevel::Default,
+            dictionary: swapvec::ZstdDictionary::Train {
+                batches: 32,
+                max_size: 4096,
+            },
+        }),
+        ..common::config(8)
+    });
+    v.consume(0..200u64).await.unwrap();
+    // Batches are kept in memory until there are enough to train
+    assert!(!v.written_to_file());
+    v.consume(200..999u64).await.unwrap();
+    assert!(v.written_to_file());
+    let read_back: Vec<u64> = v.into_stream().await.map(|x| x.unwrap()).collect().await;
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
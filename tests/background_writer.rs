@@ -0,0 +1,84 @@
+mod common;
+
+use std::sync::Arc;
+
+use swapvec::{Compress, Compression, CompressionError, SwapVec, SwapVecConfig, SwapVecError};
+
+#[derive(Debug)]
+struct BrokenCompression;
+
+impl Compress for BrokenCompression {
+    fn compress(&self, _block: &[u8], _output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        Err(CompressionError::new("broken"))
+    }
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        output.extend_from_slice(block);
+        Ok(())
+    }
+}
+
+fn config() -> SwapVecConfig {
+    SwapVecConfig {
+        background_writer: Some(2),
+        ..common::config(8)
+    }
+}
+
+#[test]
+fn write_read_back() {
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 999);
+    assert_eq!(v.batches_written(), 124);
+
+    // Waits for the batches written so far, keeps the thread running
+    assert_eq!(v.get(100).unwrap(), Some(&100));
+    let sum: u64 = v.iter().map(|x| x.unwrap()).sum();
+    assert_eq!(sum, (0..999u64).sum::<u64>());
+    v.consume(999..2000u64).unwrap();
+
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..2000).collect::<Vec<_>>());
+}
+
+#[test]
+fn persist() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("background.swapvec");
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    v.persist(&path).unwrap();
+
+    let v: SwapVec<u64> = SwapVec::open(&path, config()).unwrap();
+    assert_eq!(v.len(), 999);
+}
+
+#[test]
+fn error_on_push() {
+    let config = SwapVecConfig {
+        compression: Some(Compression::Custom(Arc::new(BrokenCompression))),
+        ..config()
+    };
+    let mut v = SwapVec::with_config(config);
+    let result = v.consume(0..999u64);
+    assert!(matches!(result, Err(SwapVecError::Compression(_))));
+}
+
+#[test]
+fn error_on_iteration() {
+    let config = SwapVecConfig {
+        compression: Some(Compression::Custom(Arc::new(BrokenCompression))),
+        ..config()
+    };
+    let mut v = SwapVec::with_config(config);
+    // Exactly one batch is written, by the last push
+    v.consume(0..16u64).unwrap();
+    v.push(16).unwrap();
+    let mut iterator = v.into_iter();
+    assert!(matches!(
+        iterator.next(),
+        Some(Err(SwapVecError::Compression(_)))
+    ));
+    assert!(iterator.next().is_none());
+}
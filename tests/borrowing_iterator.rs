@@ -0,0 +1,56 @@
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[test]
+fn iterate_and_push() {
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+
+    let read_back: Vec<u64> = v.iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+
+    v.consume(999..1500).unwrap();
+    let read_back: Vec<u64> = v.iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1500).collect::<Vec<_>>());
+
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1500).collect::<Vec<_>>());
+}
+
+#[test]
+fn iterate_without_file() {
+    let mut v = SwapVec::default();
+    v.consume(0..10u64).unwrap();
+    assert!(!v.written_to_file());
+    let read_back: Vec<u64> = v.iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn stop_after_error() {
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        named_file: true,
+        ..SwapVecConfig::default()
+    });
+    v.consume(0..999u64).unwrap();
+
+    // Corrupt the element 1 in the first batch
+    let path = v.file_path().unwrap();
+    let mut bytes = std::fs::read(path).unwrap();
+    let pattern: Vec<u8> = [1u64, 2u64].iter().flat_map(|x| x.to_le_bytes()).collect();
+    let position = bytes.windows(16).position(|w| w == pattern).unwrap();
+    bytes[position] ^= 0xff;
+    std::fs::write(path, bytes).unwrap();
+
+    let mut iterator = v.iter();
+    assert!(iterator.next().unwrap().is_err());
+    assert!(iterator.next().is_none());
+}
@@ -0,0 +1,94 @@
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[test]
+fn swap_after_bytes() {
+    let config = SwapVecConfig {
+        swap_after: usize::MAX,
+        batch_size: usize::MAX,
+        swap_after_bytes: Some(4096),
+        batch_bytes: Some(1024),
+        ..SwapVecConfig::default()
+    };
+
+    let data: Vec<String> = (0..500).map(|i| "x".repeat(i % 50)).collect();
+    let mut v = SwapVec::with_config(config);
+    v.consume(data.clone().into_iter()).unwrap();
+
+    assert!(v.written_to_file());
+    assert!(v.batches_written() > 1);
+
+    let read_back: Vec<String> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, data);
+}
+
+#[test]
+fn custom_size_estimator() {
+    let config = SwapVecConfig {
+        swap_after: usize::MAX,
+        batch_size: usize::MAX,
+        swap_after_bytes: Some(100),
+        batch_bytes: Some(10),
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.set_size_estimator(|_: &u64| 1);
+    v.consume(0..100).unwrap();
+    assert!(!v.written_to_file());
+
+    v.push(100).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.batches_written(), 1);
+    assert!(v.file_size().unwrap() > bincode_len(10));
+
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..101).collect::<Vec<_>>());
+}
+
+// Length prefix plus `n` little endian u64
+fn bincode_len(n: usize) -> usize {
+    8 + n * 8
+}
+
+#[test]
+fn swap_after_bytes_only() {
+    // Only the byte budget is set, batches are never full by count
+    let config = SwapVecConfig {
+        swap_after_bytes: Some(256 * 1024),
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.set_size_estimator(|s: &String| s.len());
+    for i in 0..64 {
+        v.push(i.to_string().repeat(64 * 1024)).unwrap();
+    }
+    assert!(v.written_to_file());
+    // At most the budget is left in memory
+    assert!(v.file_size().unwrap() > 60 * 64 * 1024);
+
+    let read_back: Vec<String> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back.len(), 64);
+    assert_eq!(read_back[63], "63".repeat(64 * 1024));
+}
+
+#[test]
+fn changing_size_estimate() {
+    let config = SwapVecConfig {
+        swap_after_bytes: Some(100),
+        batch_bytes: Some(10),
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    // A different estimate every time must not unbalance the sum
+    let calls = std::sync::atomic::AtomicUsize::new(0);
+    v.set_size_estimator(move |_: &u64| {
+        calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed) % 7
+    });
+    v.consume(0..1000).unwrap();
+    assert!(v.written_to_file());
+
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1000).collect::<Vec<_>>());
+}
@@ -0,0 +1,11 @@
+use swapvec::SwapVecConfig;
+
+/// Swap after a few elements in small batches,
+/// so even short tests write several batches.
+pub fn config(batch_size: usize) -> SwapVecConfig {
+    SwapVecConfig {
+        swap_after: 16,
+        batch_size,
+        ..SwapVecConfig::default()
+    }
+}
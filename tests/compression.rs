@@ -1,4 +1,6 @@
-use swapvec::{Compression, CompressionLevel, SwapVec, SwapVecConfig};
+#[cfg(feature = "deflate")]
+use swapvec::CompressionLevel;
+use swapvec::{Compression, SwapVec, SwapVecConfig};
 
 #[test]
 fn write_and_read_back_with_compression() {
@@ -6,9 +8,13 @@ fn write_and_read_back_with_compression() {
 
     let compression_configs: Vec<Option<Compression>> = vec![
         None,
+        #[cfg(feature = "lz4")]
         Some(Compression::Lz4),
+        #[cfg(feature = "deflate")]
         Some(Compression::Deflate(CompressionLevel::Fast)),
+        #[cfg(feature = "deflate")]
         Some(Compression::Deflate(CompressionLevel::Default)),
+        #[cfg(feature = "deflate")]
         Some(Compression::Deflate(CompressionLevel::Slow)),
     ];
 
@@ -17,6 +23,7 @@ fn write_and_read_back_with_compression() {
             compression: compression.clone(),
             swap_after: 16,
             batch_size: 8,
+            ..SwapVecConfig::default()
         };
         let mut v = SwapVec::with_config(config);
         v.consume(data.iter().copied()).unwrap();
@@ -1,29 +1,35 @@
-use swapvec::{Compress, CompressBoxedClone, Compression, SwapVec, SwapVecConfig};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
-#[derive(Debug)]
+#[allow(deprecated)]
+use swapvec::CompressBoxedClone;
+use swapvec::{
+    Compress, Compression, CompressionError, SwapVec, SwapVecConfig, SwapVecError, SwapVecIter,
+};
+
+#[derive(Debug, Clone)]
 struct MyCompression;
 
 impl Compress for MyCompression {
-    fn compress(&self, block: Vec<u8>) -> Vec<u8> {
-        block
-    }
-    fn decompress(&self, block: Vec<u8>) -> Result<Vec<u8>, ()> {
-        Ok(block)
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        output.extend_from_slice(block);
+        Ok(())
     }
-}
-
-impl CompressBoxedClone for MyCompression {
-    fn boxed_clone(&self) -> Box<dyn CompressBoxedClone> {
-        Box::new(MyCompression)
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        output.extend_from_slice(block);
+        Ok(())
     }
 }
 
 #[test]
 fn custom_compression() {
     let config = SwapVecConfig {
-        compression: Some(Compression::Custom(Box::new(MyCompression))),
+        compression: Some(Compression::Custom(Arc::new(MyCompression))),
         swap_after: 16,
         batch_size: 5,
+        ..SwapVecConfig::default()
     };
 
     let vector: Vec<u64> = (0..999).collect();
@@ -32,4 +38,100 @@ fn custom_compression() {
     assert!(v.written_to_file());
     let vector_read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
     assert_eq!(vector, vector_read_back);
+
+    // Provided for every Clone type
+    #[allow(deprecated)]
+    let boxed: Box<dyn CompressBoxedClone> = MyCompression.boxed_clone();
+    assert_eq!(format!("{:?}", boxed), "MyCompression");
+}
+
+#[derive(Debug, Default)]
+struct CountingCompression {
+    compressed: AtomicUsize,
+    decompressed: AtomicUsize,
+}
+
+impl Compress for CountingCompression {
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        self.compressed.fetch_add(1, Ordering::Relaxed);
+        output.extend_from_slice(block);
+        Ok(())
+    }
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        self.decompressed.fetch_add(1, Ordering::Relaxed);
+        output.extend_from_slice(block);
+        Ok(())
+    }
+}
+
+#[test]
+fn shared_state() {
+    let counter = Arc::new(CountingCompression::default());
+    let config = SwapVecConfig {
+        compression: Some(Compression::Custom(counter.clone())),
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.consume(0..999u64).unwrap();
+    let batches = v.batches_written();
+    assert_eq!(counter.compressed.load(Ordering::Relaxed), batches);
+    assert_eq!(v.into_iter().count(), 999);
+    // Elements left in memory are written before iterating
+    let compressed = counter.compressed.load(Ordering::Relaxed);
+    assert!(compressed > batches);
+    assert_eq!(counter.decompressed.load(Ordering::Relaxed), compressed);
+}
+
+#[derive(Debug)]
+struct BrokenDecompression;
+
+impl Compress for BrokenDecompression {
+    fn compress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        output.extend_from_slice(block);
+        Ok(())
+    }
+    fn decompress(&self, _block: &[u8], _output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        Err(CompressionError::new("broken on purpose"))
+    }
+    fn codec_id(&self) -> u8 {
+        200
+    }
+}
+
+#[test]
+fn decompression_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("broken.swapvec");
+    let config = || SwapVecConfig {
+        compression: Some(Compression::Custom(Arc::new(BrokenDecompression))),
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    v.persist(&path).unwrap();
+
+    let mut iterator = SwapVecIter::<u64>::open(&path, config()).unwrap();
+    match iterator.next() {
+        Some(Err(SwapVecError::Decompression(e))) => {
+            assert_eq!(e.to_string(), "broken on purpose")
+        }
+        other => panic!("Unexpected {:?}", other),
+    }
+    assert!(iterator.next().is_none());
+
+    // The codec id is recorded in the file
+    let other_codec = SwapVecConfig {
+        compression: Some(Compression::Custom(Arc::new(MyCompression))),
+        ..config()
+    };
+    assert!(matches!(
+        SwapVecIter::<u64>::open(&path, other_codec),
+        Err(SwapVecError::InvalidFormat)
+    ));
 }
@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    id: u64,
+    // Not written to disk, so lost once swapped
+    #[serde(skip)]
+    cache: Option<String>,
+}
+
+fn entries(count: u64) -> impl Iterator<Item = Entry> {
+    (0..count).map(|id| Entry {
+        id,
+        cache: Some(id.to_string()),
+    })
+}
+
+#[test]
+fn skipped_fields_kept_in_memory() {
+    let mut v = SwapVec::default();
+    v.consume(entries(10)).unwrap();
+    assert!(!v.written_to_file());
+
+    for (id, entry) in v.into_iter().enumerate() {
+        let entry = entry.unwrap();
+        assert_eq!(entry.id, id as u64);
+        assert_eq!(entry.cache, Some(id.to_string()));
+    }
+}
+
+#[test]
+fn skipped_fields_from_both_ends() {
+    let mut v = SwapVec::default();
+    v.consume(entries(10)).unwrap();
+
+    let mut iterator = v.into_iter();
+    assert_eq!(iterator.next_back().unwrap().unwrap().cache.unwrap(), "9");
+    assert_eq!(iterator.next().unwrap().unwrap().cache.unwrap(), "0");
+    assert_eq!(iterator.len(), 8);
+}
+
+#[test]
+fn skipped_fields_swapped() {
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+    let mut v = SwapVec::with_config(config);
+    v.consume(entries(100)).unwrap();
+    assert!(v.written_to_file());
+
+    let ids: Vec<u64> = v
+        .into_iter()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            assert!(entry.cache.is_none());
+            entry.id
+        })
+        .collect();
+    assert_eq!(ids, (0..100).collect::<Vec<_>>());
+}
@@ -0,0 +1,34 @@
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[test]
+fn len() {
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+    let mut v = SwapVec::with_config(config);
+    assert!(v.is_empty());
+    v.consume(0..10u64).unwrap();
+    assert_eq!(v.len(), 10);
+    v.consume(10..999u64).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 999);
+
+    let mut iterator = v.into_iter();
+    assert_eq!(iterator.len(), 999);
+    assert_eq!(iterator.size_hint(), (999, Some(999)));
+    iterator.next().unwrap().unwrap();
+    iterator.next_back().unwrap().unwrap();
+    assert_eq!(iterator.len(), 997);
+
+    iterator.seek(500).unwrap();
+    assert_eq!(iterator.len(), 499);
+    iterator.next_back().unwrap().unwrap();
+    assert_eq!(iterator.len(), 498);
+
+    iterator.reset();
+    let read_back: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back.len(), 999);
+    assert!(iterator.is_empty());
+}
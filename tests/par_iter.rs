@@ -0,0 +1,48 @@
+#![cfg(feature = "rayon")]
+
+mod common;
+
+use rayon::iter::ParallelIterator;
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[test]
+fn same_as_sequential() {
+    let mut v = SwapVec::with_config(common::config(8));
+    v.consume(0..1003u64).unwrap();
+    assert!(v.written_to_file());
+
+    let read_back: Vec<u64> = v.par_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1003).collect::<Vec<_>>());
+
+    // Still usable afterwards
+    v.push(1003).unwrap();
+    assert_eq!(v.par_iter().count(), 1004);
+}
+
+#[test]
+fn heavy_processing() {
+    let mut v = SwapVec::with_config(common::config(8));
+    v.consume((0..10_000u64).map(|i| i.to_string())).unwrap();
+    let digits: usize = v.par_iter().map(|x| x.unwrap().len()).sum();
+    let expected: usize = (0..10_000u64).map(|i| i.to_string().len()).sum();
+    assert_eq!(digits, expected);
+}
+
+#[test]
+fn in_memory() {
+    let mut v = SwapVec::default();
+    v.consume(0..99u64).unwrap();
+    assert!(!v.written_to_file());
+    assert_eq!(v.par_iter().map(|x| x.unwrap()).sum::<u64>(), 4851);
+}
+
+#[test]
+fn background_writer() {
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        background_writer: Some(2),
+        ..common::config(8)
+    });
+    v.consume(0..1003u64).unwrap();
+    let read_back: Vec<u64> = v.par_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1003).collect::<Vec<_>>());
+}
@@ -0,0 +1,54 @@
+#![cfg(feature = "deflate")]
+
+mod common;
+
+use swapvec::{Compression, CompressionLevel, SwapVec, SwapVecConfig};
+
+fn config(compression_threads: usize) -> SwapVecConfig {
+    SwapVecConfig {
+        compression: Some(Compression::Deflate(CompressionLevel::Slow)),
+        compression_threads,
+        ..common::config(8)
+    }
+}
+
+#[test]
+fn parallel_compression() {
+    let mut v = SwapVec::with_config(config(4));
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 999);
+    // Written in groups of 4 batches
+    assert_eq!(v.batches_written() % 4, 0);
+    assert_eq!(v.compression_stats().len(), v.batches_written());
+    assert_eq!(v.get(3).unwrap(), Some(&3));
+
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
+
+#[test]
+fn parallel_background_writer() {
+    let config = SwapVecConfig {
+        background_writer: Some(2),
+        ..config(3)
+    };
+    let mut v = SwapVec::with_config(config);
+    v.consume(0..999u64).unwrap();
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
+
+#[test]
+fn same_file_as_sequential() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut files = Vec::new();
+    for threads in [1, 4] {
+        let path = dir.path().join(format!("threads-{}.swapvec", threads));
+        let mut v = SwapVec::with_config(config(threads));
+        v.consume(0..999u64).unwrap();
+        v.persist(&path).unwrap();
+        files.push(std::fs::read(&path).unwrap());
+    }
+    assert_eq!(files[0], files[1]);
+}
@@ -0,0 +1,162 @@
+mod common;
+
+use swapvec::{SwapFileInfo, SwapVec, SwapVecConfig, SwapVecError, SwapVecIter};
+
+#[test]
+fn persist_and_reopen() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("persisted.swapvec");
+
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        named_file: true,
+        ..common::config(5)
+    });
+    v.consume(0..999u64).unwrap();
+    let temp_path = v.file_path().unwrap().to_path_buf();
+    v.persist(&path).unwrap();
+    assert!(!temp_path.exists());
+
+    let read_back: Vec<u64> = SwapVecIter::open(&path, common::config(5))
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+
+    let mut v: SwapVec<u64> = SwapVec::open(&path, common::config(5)).unwrap();
+    v.consume(999..1500).unwrap();
+    v.persist(&path).unwrap();
+
+    let read_back: Vec<u64> = SwapVecIter::open(&path, common::config(5))
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, (0..1500).collect::<Vec<_>>());
+}
+
+#[test]
+fn persist_without_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("small.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..3u64).unwrap();
+    assert!(!v.written_to_file());
+    v.persist(&path).unwrap();
+
+    let read_back: Vec<u64> = SwapVecIter::open(&path, common::config(5))
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, vec![0, 1, 2]);
+}
+
+#[test]
+fn resume_after_abort() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("aborted.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..10u64).unwrap();
+    v.persist(&path).unwrap();
+
+    // Dropped without persisting, the batch index is missing
+    let mut v: SwapVec<u64> = SwapVec::open(&path, common::config(5)).unwrap();
+    v.consume(10..22).unwrap();
+    assert_eq!(v.batches_written(), 4);
+    drop(v);
+
+    let read_back: Vec<u64> = SwapVecIter::open(&path, common::config(5))
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, (0..20).collect::<Vec<_>>());
+}
+
+#[test]
+fn open_without_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("unchanged.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..10u64).unwrap();
+    v.persist(&path).unwrap();
+    let size = std::fs::metadata(&path).unwrap().len();
+
+    // Only writing drops the batch index
+    let v: SwapVec<u64> = SwapVec::open(&path, common::config(5)).unwrap();
+    assert_eq!(v.len(), 10);
+    drop(v);
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    assert!(info.complete);
+    assert_eq!(info.file_size, size);
+}
+
+#[test]
+fn persist_after_incomplete_frame() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("killed.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..10u64).unwrap();
+    v.persist(&path).unwrap();
+    // Batch index replaced by a longer, partially written frame
+    let mut bytes = std::fs::read(&path).unwrap();
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    bytes.truncate(bytes.len() - 32 - 8 * 4 * info.batches);
+    bytes.extend_from_slice(&[0xff; 500]);
+    std::fs::write(&path, bytes).unwrap();
+
+    let v: SwapVec<u64> = SwapVec::open(&path, common::config(5)).unwrap();
+    assert_eq!(v.len(), 10);
+    v.persist(&path).unwrap();
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    assert!(info.complete);
+    assert_eq!(info.elements, 10);
+}
+
+#[test]
+fn truncated_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("persisted.swapvec");
+    let truncated = dir.path().join("truncated.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..100u64).unwrap();
+    v.persist(&path).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    let index_len = 32 + 8 * 4 * info.batches;
+
+    // Cut inside the trailer, and inside the index
+    for cut in [8, index_len - 40] {
+        std::fs::write(&truncated, &bytes[..bytes.len() - cut]).unwrap();
+        let info = SwapFileInfo::from_path(&truncated).unwrap();
+        assert!(!info.complete);
+        assert_eq!(info.elements, 100);
+
+        let mut v: SwapVec<u64> = SwapVec::open(&truncated, common::config(5)).unwrap();
+        assert_eq!(v.len(), 100);
+        v.push(100).unwrap();
+        v.persist(&truncated).unwrap();
+        let read_back: Vec<u64> = SwapVecIter::open(&truncated, common::config(5))
+            .unwrap()
+            .map(|x| x.unwrap())
+            .collect();
+        assert_eq!(read_back, (0..101).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn open_with_wrong_type() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("typed.swapvec");
+
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..10u64).unwrap();
+    v.persist(&path).unwrap();
+
+    assert!(matches!(
+        SwapVecIter::<String>::open(&path, common::config(5)),
+        Err(SwapVecError::InvalidFormat)
+    ));
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use swapvec::{SwapVec, SwapVecConfig, SwapVecIter};
+
+fn config() -> SwapVecConfig {
+    SwapVecConfig {
+        prefetch: Some(3),
+        #[cfg(feature = "lz4")]
+        compression: Some(swapvec::Compression::Lz4),
+        ..common::config(8)
+    }
+}
+
+#[test]
+fn prefetch() {
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+    let mut iterator = v.into_iter();
+    let read_back: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+
+    iterator.reset();
+    let read_back: Vec<u64> = iterator.by_ref().take(10).map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..10).collect::<Vec<_>>());
+
+    iterator.seek(500).unwrap();
+    assert_eq!(iterator.next().unwrap().unwrap(), 500);
+    assert_eq!(iterator.next_back().unwrap().unwrap(), 998);
+    let read_back: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (501..998).collect::<Vec<_>>());
+}
+
+#[test]
+fn prefetch_both_ends() {
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    let mut iterator = v.into_iter();
+    let mut front = 0;
+    let mut back = 999;
+    while let Some(value) = iterator.next() {
+        assert_eq!(value.unwrap(), front);
+        front += 1;
+        if let Some(value) = iterator.next_back() {
+            back -= 1;
+            assert_eq!(value.unwrap(), back);
+        }
+    }
+    assert_eq!(front, back);
+}
+
+#[test]
+fn prefetch_persisted() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("prefetch.swapvec");
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    v.persist(&path).unwrap();
+
+    let iterator: SwapVecIter<u64> = SwapVecIter::open(&path, config()).unwrap();
+    let read_back: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
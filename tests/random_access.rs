@@ -0,0 +1,48 @@
+mod common;
+
+use swapvec::{SwapVec, SwapVecConfig};
+
+fn config() -> SwapVecConfig {
+    SwapVecConfig {
+        batch_cache_size: 2,
+        ..common::config(5)
+    }
+}
+
+#[test]
+fn get() {
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+
+    for i in [0, 998, 5, 4, 500, 501, 3, 997, 0] {
+        assert_eq!(v.get(i).unwrap(), Some(&(i as u64)));
+    }
+    assert_eq!(v.get(999).unwrap(), None);
+
+    // Batches written after the first read are found as well
+    v.consume(999..1500).unwrap();
+    assert_eq!(v.get(1200).unwrap(), Some(&1200));
+}
+
+#[test]
+fn seek() {
+    let mut v = SwapVec::with_config(config());
+    v.consume(0..999u64).unwrap();
+    let mut iterator = v.into_iter();
+
+    iterator.seek(512).unwrap();
+    assert_eq!(iterator.next().unwrap().unwrap(), 512);
+    assert_eq!(iterator.next().unwrap().unwrap(), 513);
+
+    iterator.seek(997).unwrap();
+    let rest: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(rest, vec![997, 998]);
+
+    iterator.seek(3).unwrap();
+    let rest: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(rest, (3..999).collect::<Vec<_>>());
+
+    iterator.seek(999).unwrap();
+    assert!(iterator.next().is_none());
+}
@@ -6,6 +6,7 @@ fn reset_with_file() {
         compression: None,
         swap_after: 16,
         batch_size: 5,
+        ..SwapVecConfig::default()
     };
 
     let vector: Vec<u64> = (0..999).collect();
@@ -23,3 +24,21 @@ fn reset_with_file() {
     let vector_read_back2: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
     assert_eq!(vector, vector_read_back2);
 }
+
+#[test]
+fn reset_without_file() {
+    let mut v = SwapVec::default();
+    v.consume(0..10u64).unwrap();
+    assert!(!v.written_to_file());
+
+    let mut iterator = v.into_iter();
+    let vector_read_back: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(vector_read_back, (0..10).collect::<Vec<_>>());
+
+    iterator.reset();
+    let vector_read_back2: Vec<u64> = iterator.by_ref().map(|x| x.unwrap()).collect();
+    assert_eq!(vector_read_back, vector_read_back2);
+
+    iterator.seek(7).unwrap();
+    assert_eq!(iterator.next().unwrap().unwrap(), 7);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use swapvec::SwapVec;
+
+#[test]
+fn reverse() {
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+
+    let read_back: Vec<u64> = v.into_iter().rev().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn reverse_in_memory() {
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..10u64).unwrap();
+    assert!(!v.written_to_file());
+
+    let read_back: Vec<u64> = v.into_iter().rev().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..10).rev().collect::<Vec<_>>());
+}
+
+#[test]
+fn both_ends() {
+    let mut v = SwapVec::with_config(common::config(5));
+    v.consume(0..999u64).unwrap();
+    let mut iterator = v.into_iter();
+
+    let mut front = Vec::new();
+    let mut back = Vec::new();
+    while let Some(x) = iterator.next() {
+        front.push(x.unwrap());
+        match iterator.next_back() {
+            Some(x) => back.push(x.unwrap()),
+            None => break,
+        }
+    }
+    back.reverse();
+    front.extend(back);
+    assert_eq!(front, (0..999).collect::<Vec<_>>());
+
+    iterator.reset();
+    assert_eq!(iterator.next_back().unwrap().unwrap(), 998);
+    assert_eq!(iterator.next().unwrap().unwrap(), 0);
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use std::{sync::Arc, thread};
+
+use serde::{Deserialize, Serialize, Serializer};
+use swapvec::{Compress, Compression, CompressionError, SwapVec, SwapVecConfig, SwapVecError};
+
+#[derive(Debug)]
+struct BrokenCompression;
+
+impl Compress for BrokenCompression {
+    fn compress(&self, _block: &[u8], _output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        Err(CompressionError::new("broken"))
+    }
+    fn decompress(&self, block: &[u8], output: &mut Vec<u8>) -> Result<(), CompressionError> {
+        output.extend_from_slice(block);
+        Ok(())
+    }
+}
+
+#[test]
+fn many_producers() {
+    let v: SwapVec<(u64, u64)> = SwapVec::with_config(common::config(8));
+    let (sender, collector) = v.sender();
+    let producers: Vec<_> = (0..8)
+        .map(|producer| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for i in 0..1000 {
+                    sender.send((producer, i)).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+    producers.into_iter().for_each(|p| p.join().unwrap());
+
+    let v = collector.into_swapvec().unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 8000);
+    let mut read_back: Vec<(u64, u64)> = v.into_iter().map(|x| x.unwrap()).collect();
+    // Every producer's elements keep their order
+    for producer in 0..8 {
+        let own: Vec<u64> = read_back
+            .iter()
+            .filter(|(p, _)| *p == producer)
+            .map(|(_, i)| *i)
+            .collect();
+        assert_eq!(own, (0..1000).collect::<Vec<_>>());
+    }
+    read_back.sort();
+    assert_eq!(read_back.len(), 8000);
+}
+
+#[test]
+fn finish() {
+    let (sender, collector) = SwapVec::with_config(common::config(8)).sender();
+    for i in 0..100u64 {
+        sender.send(i).unwrap();
+    }
+    drop(sender);
+    let read_back: Vec<u64> = collector.finish().unwrap().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn collector_error() {
+    let config = SwapVecConfig {
+        compression: Some(Compression::Custom(Arc::new(BrokenCompression))),
+        ..common::config(8)
+    };
+    let (sender, collector) = SwapVec::with_config(config).sender();
+    let result: Result<(), SwapVecError> = (0..1000u64).try_for_each(|i| sender.send(i));
+    assert!(result.is_err());
+    drop(sender);
+    assert!(matches!(
+        collector.finish(),
+        Err(SwapVecError::Compression(_))
+    ));
+}
+
+// Serializing panics
+#[derive(Deserialize)]
+struct Panicking(u64);
+
+impl Serialize for Panicking {
+    fn serialize<S: Serializer>(&self, _serializer: S) -> Result<S::Ok, S::Error> {
+        panic!("cannot serialize {}", self.0);
+    }
+}
+
+#[test]
+fn collector_panic() {
+    let (sender, collector) = SwapVec::with_config(common::config(8)).sender();
+    let result: Result<(), SwapVecError> = (0..1000).try_for_each(|i| sender.send(Panicking(i)));
+    assert!(result.is_err());
+    drop(sender);
+    assert!(thread::spawn(move || collector.finish()).join().is_err());
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn sink() {
+    use futures_util::{stream, SinkExt, StreamExt};
+
+    let (mut sender, collector) = SwapVec::with_config(common::config(8)).sender();
+    let mut elements = stream::iter(0..1000u64).map(Ok);
+    sender.send_all(&mut elements).await.unwrap();
+    drop(sender);
+    let read_back: Vec<u64> = collector.finish().unwrap().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..1000).collect::<Vec<_>>());
+}
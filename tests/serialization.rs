@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use swapvec::{Serialization, SwapVec, SwapVecConfig, SwapVecError};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Number(u64),
+    Text(String),
+}
+
+fn values() -> Vec<Value> {
+    (0..1000u64)
+        .map(|i| {
+            if i % 3 == 0 {
+                Value::Text(i.to_string())
+            } else {
+                Value::Number(i)
+            }
+        })
+        .collect()
+}
+
+fn write_read_back<T>(serialization: Serialization, data: Vec<T>) -> Result<Vec<T>, SwapVecError>
+where
+    T: Serialize + for<'a> Deserialize<'a>,
+{
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 8,
+        serialization,
+        ..SwapVecConfig::default()
+    };
+    let mut v = SwapVec::with_config(config);
+    v.consume(data.into_iter())?;
+    assert!(v.written_to_file());
+    v.into_iter().collect()
+}
+
+#[test]
+fn bincode() {
+    let data: Vec<(u64, String)> = (0..1000).map(|i| (i, i.to_string())).collect();
+    let read_back = write_read_back(Serialization::Bincode, data.clone()).unwrap();
+    assert_eq!(read_back, data);
+}
+
+#[test]
+fn bincode_untagged_fails() {
+    let result = write_read_back(Serialization::Bincode, values());
+    assert!(matches!(result, Err(SwapVecError::SerializationFailed(_))));
+}
+
+#[cfg(feature = "postcard")]
+#[test]
+fn postcard() {
+    let data: Vec<(u64, String)> = (0..1000).map(|i| (i, i.to_string())).collect();
+    let read_back = write_read_back(Serialization::Postcard, data.clone()).unwrap();
+    assert_eq!(read_back, data);
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_untagged() {
+    let read_back = write_read_back(Serialization::MessagePack, values()).unwrap();
+    assert_eq!(read_back, values());
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_untagged() {
+    let read_back = write_read_back(Serialization::Cbor, values()).unwrap();
+    assert_eq!(read_back, values());
+}
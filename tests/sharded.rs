@@ -0,0 +1,83 @@
+mod common;
+
+use std::thread;
+
+use swapvec::ShardedSwapVec;
+
+#[test]
+fn many_producers() {
+    let v: ShardedSwapVec<(u64, u64)> = ShardedSwapVec::with_config(common::config(8), 4);
+    assert_eq!(v.shard_count(), 4);
+    thread::scope(|s| {
+        for producer in 0..8 {
+            let v = &v;
+            s.spawn(move || {
+                for i in 0..1000 {
+                    v.push((producer, i)).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(v.len(), 8000);
+
+    let read_back: Vec<(u64, u64)> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back.len(), 8000);
+    // Every producer's elements keep their order
+    for producer in 0..8 {
+        let own: Vec<u64> = read_back
+            .iter()
+            .filter(|(p, _)| *p == producer)
+            .map(|(_, i)| *i)
+            .collect();
+        assert_eq!(own, (0..1000).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn interleaved() {
+    let v: ShardedSwapVec<u64> = ShardedSwapVec::with_config(common::config(8), 3);
+    // One producer after the other, each on its own thread
+    for producer in 0..6 {
+        let v = &v;
+        thread::scope(|s| {
+            s.spawn(move || v.consume(producer * 100..(producer + 1) * 100).unwrap());
+        });
+    }
+    v.push(600).unwrap();
+
+    let iterator = v.into_interleaved_iter();
+    assert_eq!(iterator.len(), 601);
+    let read_back: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..601).collect::<Vec<_>>());
+}
+
+#[test]
+fn empty() {
+    let v: ShardedSwapVec<u64> = ShardedSwapVec::new(0);
+    assert_eq!(v.shard_count(), 1);
+    assert!(v.is_empty());
+    assert_eq!(v.into_interleaved_iter().count(), 0);
+}
+
+#[test]
+fn own_shard_per_thread() {
+    // Threads which pushed to another instance before
+    let other: ShardedSwapVec<u64> = ShardedSwapVec::new(4);
+    thread::scope(|s| {
+        for _ in 0..3 {
+            s.spawn(|| other.push(0).unwrap());
+        }
+    });
+
+    let v: ShardedSwapVec<u64> = ShardedSwapVec::with_config(common::config(8), 4);
+    for producer in 0..4 {
+        let v = &v;
+        thread::scope(|s| {
+            s.spawn(move || v.consume(producer * 100..(producer + 1) * 100).unwrap());
+        });
+    }
+    // Every thread got the next shard of its own, which
+    // are concatenated in the order the threads first pushed
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..400).collect::<Vec<_>>());
+}
@@ -0,0 +1,182 @@
+use swapvec::{SortedSwapVec, SwapVec, SwapVecConfig};
+
+fn config(merge_fan_in: usize) -> SwapVecConfig {
+    SwapVecConfig {
+        swap_after: 100,
+        batch_size: 8,
+        merge_fan_in,
+        ..SwapVecConfig::default()
+    }
+}
+
+// Deterministic pseudo-random numbers
+fn shuffled(count: u64) -> impl Iterator<Item = u64> {
+    (0..count).map(|i| i.wrapping_mul(6364136223846793005).rotate_left(17) % 1000)
+}
+
+#[test]
+fn sorted_iter() {
+    // 25 runs, merged in two passes with a fan-in of 4
+    for fan_in in [2, 4, 64] {
+        let mut v = SwapVec::with_config(config(fan_in));
+        v.consume(shuffled(2500)).unwrap();
+        assert!(v.written_to_file());
+
+        let iterator = v.into_sorted_iter();
+        assert_eq!(iterator.len(), 2500);
+        let sorted: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+        let mut expected: Vec<u64> = shuffled(2500).collect();
+        expected.sort();
+        assert_eq!(sorted, expected);
+    }
+}
+
+#[test]
+fn stable() {
+    let mut v = SwapVec::with_config(config(3));
+    v.consume(shuffled(1234).enumerate()).unwrap();
+
+    let sorted: Vec<(usize, u64)> = v
+        .sort_by_key(|(_, value)| *value)
+        .map(|x| x.unwrap())
+        .collect();
+    let mut expected: Vec<(usize, u64)> = shuffled(1234).enumerate().collect();
+    expected.sort_by_key(|(_, value)| *value);
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn unstable_descending() {
+    let mut v = SwapVec::with_config(config(2));
+    v.consume(shuffled(999)).unwrap();
+
+    let sorted: Vec<u64> = v
+        .sort_unstable_by(|a, b| b.cmp(a))
+        .map(|x| x.unwrap())
+        .collect();
+    let mut expected: Vec<u64> = shuffled(999).collect();
+    expected.sort_by(|a, b| b.cmp(a));
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn in_memory() {
+    let mut v = SwapVec::default();
+    v.consume(shuffled(99)).unwrap();
+    assert!(!v.written_to_file());
+
+    let sorted: Vec<u64> = v.into_sorted_iter().map(|x| x.unwrap()).collect();
+    let mut expected: Vec<u64> = shuffled(99).collect();
+    expected.sort();
+    assert_eq!(sorted, expected);
+
+    let v: SwapVec<u64> = SwapVec::default();
+    assert_eq!(v.into_sorted_iter().count(), 0);
+}
+
+#[test]
+fn runs_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        named_file: true,
+        ..config(2)
+    });
+    v.consume(shuffled(999)).unwrap();
+
+    let mut iterator = v.into_sorted_iter();
+    assert_eq!(iterator.next().unwrap().unwrap(), 0);
+    // Only the file of the last merge pass is left
+    assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+    drop(iterator);
+    assert_eq!(dir.path().read_dir().unwrap().count(), 0);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn compressed() {
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        compression: Some(swapvec::Compression::Lz4),
+        ..config(4)
+    });
+    v.consume(shuffled(2000)).unwrap();
+    let sorted: Vec<u64> = v.into_sorted_iter().map(|x| x.unwrap()).collect();
+    assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(sorted.len(), 2000);
+}
+
+#[test]
+fn sorted_while_pushing() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = SwapVecConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        named_file: true,
+        ..config(4)
+    };
+    let mut v = SortedSwapVec::new(config, u64::cmp);
+    v.consume(shuffled(2500)).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.len(), 2500);
+    // The runs are the only file
+    assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+
+    let iterator = v.into_sorted_iter();
+    assert_eq!(iterator.len(), 2500);
+    let sorted: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    let mut expected: Vec<u64> = shuffled(2500).collect();
+    expected.sort();
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn sorted_while_pushing_byte_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = SwapVecConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        named_file: true,
+        swap_after_bytes: Some(1024),
+        batch_bytes: Some(256),
+        ..SwapVecConfig::default()
+    };
+    let mut v = SortedSwapVec::new(config, u64::cmp);
+    v.consume(shuffled(2500)).unwrap();
+    // Far less than `swap_after` elements
+    assert!(v.written_to_file());
+
+    let sorted: Vec<u64> = v.into_sorted_iter().map(|x| x.unwrap()).collect();
+    let mut expected: Vec<u64> = shuffled(2500).collect();
+    expected.sort();
+    assert_eq!(sorted, expected);
+}
+
+#[test]
+fn byte_limits() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut v = SwapVec::with_config(SwapVecConfig {
+        temp_dir: Some(dir.path().to_path_buf()),
+        named_file: true,
+        swap_after_bytes: Some(1024),
+        batch_size: 8,
+        ..SwapVecConfig::default()
+    });
+    v.consume(shuffled(999)).unwrap();
+
+    let mut iterator = v.into_sorted_iter();
+    assert_eq!(iterator.next().unwrap().unwrap(), 0);
+    // Runs of 1024 bytes have been written, not one run in memory
+    assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+    let sorted: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    assert_eq!(sorted.len(), 998);
+    assert!(sorted.windows(2).all(|w| w[0] <= w[1]));
+}
+
+#[test]
+fn sorted_while_pushing_in_memory() {
+    let mut v = SortedSwapVec::new(SwapVecConfig::default(), |a: &u64, b: &u64| b.cmp(a));
+    v.consume(shuffled(99)).unwrap();
+    assert!(!v.written_to_file());
+    let sorted: Vec<u64> = v.into_sorted_iter().map(|x| x.unwrap()).collect();
+    let mut expected: Vec<u64> = shuffled(99).collect();
+    expected.sort_by(|a, b| b.cmp(a));
+    assert_eq!(sorted, expected);
+}
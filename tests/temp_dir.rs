@@ -0,0 +1,56 @@
+use swapvec::{SwapVec, SwapVecConfig};
+
+#[test]
+fn custom_temp_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        temp_dir: Some(dir.path().to_path_buf()),
+        file_prefix: "my-prefix-".to_string(),
+        file_suffix: ".swp".to_string(),
+        named_file: true,
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    assert!(v.file_path().is_none());
+    v.consume(0..999u64).unwrap();
+
+    let path = v.file_path().unwrap().to_path_buf();
+    assert_eq!(path.parent(), Some(dir.path()));
+    let name = path.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("my-prefix-"));
+    assert!(name.ends_with(".swp"));
+    assert!(path.exists());
+
+    let iterator = v.into_iter();
+    assert_eq!(iterator.file_path(), Some(path.as_path()));
+    let read_back: Vec<u64> = iterator.map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+    assert!(!path.exists());
+}
+
+#[test]
+fn unlinked_by_default() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        temp_dir: Some(dir.path().to_path_buf()),
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.consume(0..999u64).unwrap();
+    assert!(v.written_to_file());
+    // Nothing left behind, even if the process gets killed
+    assert!(v.file_path().is_none());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+    assert_eq!(v.get(500).unwrap(), Some(&500));
+    let sum: u64 = v.iter().map(|x| x.unwrap()).sum();
+    assert_eq!(sum, (0..999u64).sum::<u64>());
+    let read_back: Vec<u64> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, (0..999).collect::<Vec<_>>());
+}
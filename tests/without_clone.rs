@@ -0,0 +1,51 @@
+use std::sync::Mutex;
+
+use swapvec::{SwapVec, SwapVecConfig};
+
+// Mutex does not implement Clone
+fn read_back(
+    iterator: impl Iterator<Item = Result<Mutex<u64>, swapvec::SwapVecError>>,
+) -> Vec<u64> {
+    iterator.map(|x| x.unwrap().into_inner().unwrap()).collect()
+}
+
+#[test]
+fn iterate_without_clone() {
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        ..SwapVecConfig::default()
+    };
+    let mut v = SwapVec::with_config(config);
+    v.consume((0..999u64).map(Mutex::new)).unwrap();
+    assert_eq!(*v.get(500).unwrap().unwrap().lock().unwrap(), 500);
+
+    let mut iterator = v.into_iter();
+    assert_eq!(read_back(iterator.by_ref()), (0..999).collect::<Vec<_>>());
+
+    // Elements kept in memory are available after reset as well
+    iterator.reset();
+    assert_eq!(
+        read_back(iterator.by_ref().rev().take(3)),
+        vec![998, 997, 996]
+    );
+    assert_eq!(iterator.len(), 996);
+}
+
+#[test]
+fn reset_without_file() {
+    let mut v = SwapVec::default();
+    v.consume((0..10u64).map(Mutex::new)).unwrap();
+    assert!(!v.written_to_file());
+
+    let mut iterator = v.into_iter();
+    assert_eq!(read_back(iterator.by_ref()), (0..10).collect::<Vec<_>>());
+
+    // Elements in memory have been moved out
+    iterator.reset();
+    assert!(matches!(
+        iterator.next(),
+        Some(Err(swapvec::SwapVecError::AlreadyConsumed))
+    ));
+    assert!(iterator.next().is_none());
+}
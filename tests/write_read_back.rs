@@ -1,4 +1,4 @@
-use swapvec::{SwapVec, SwapVecConfig};
+use swapvec::{SwapFileInfo, SwapVec, SwapVecConfig};
 
 #[test]
 fn with_file() {
@@ -6,6 +6,7 @@ fn with_file() {
         compression: None,
         swap_after: 16,
         batch_size: 5,
+        ..SwapVecConfig::default()
     };
 
     let vector: Vec<u64> = (0..999).collect();
@@ -26,6 +27,7 @@ fn without_file() {
         compression: None,
         swap_after: 1001,
         batch_size: 5,
+        ..SwapVecConfig::default()
     };
 
     let vector: Vec<u64> = (0..999).collect();
@@ -39,3 +41,28 @@ fn without_file() {
 
     assert_eq!(vector, vector_read_back);
 }
+
+#[test]
+fn inspect_file() {
+    let config = SwapVecConfig {
+        swap_after: 16,
+        batch_size: 5,
+        named_file: true,
+        ..SwapVecConfig::default()
+    };
+
+    let mut v = SwapVec::with_config(config);
+    v.consume(0..999u64).unwrap();
+
+    let path = v.file_path().unwrap().to_path_buf();
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    assert!(!info.complete);
+    assert_eq!(info.batches, v.batches_written());
+    assert_eq!(info.elements, v.batches_written() * 5);
+    assert_eq!(info.file_size, v.file_size().unwrap() as u64);
+
+    let iterator = v.into_iter();
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    assert!(info.complete);
+    drop(iterator);
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "zstd")]
+
+mod common;
+
+use swapvec::{
+    Compression, CompressionLevel, SwapFileInfo, SwapVec, SwapVecConfig, SwapVecIter,
+    ZstdDictionary,
+};
+
+fn config(level: CompressionLevel, dictionary: ZstdDictionary) -> SwapVecConfig {
+    SwapVecConfig {
+        compression: Some(Compression::Zstd { level, dictionary }),
+        ..common::config(50)
+    }
+}
+
+fn records() -> impl Iterator<Item = (String, u64)> {
+    (0..5000u64).map(|i| (format!("sensor-{}/temperature", i % 7), i * 3))
+}
+
+#[test]
+fn zstd_levels() {
+    for level in [
+        CompressionLevel::Fast,
+        CompressionLevel::Default,
+        CompressionLevel::Slow,
+        CompressionLevel::Numeric(-5),
+        CompressionLevel::Numeric(22),
+    ] {
+        let mut v = SwapVec::with_config(config(level, ZstdDictionary::None));
+        v.consume(records()).unwrap();
+        assert!(v.written_to_file());
+        let read_back: Vec<(String, u64)> = v.into_iter().map(|x| x.unwrap()).collect();
+        assert_eq!(read_back, records().collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn trained_dictionary() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dictionary.swapvec");
+    let train = || {
+        config(
+            CompressionLevel::Default,
+            ZstdDictionary::Train {
+                batches: 20,
+                max_size: 4096,
+            },
+        )
+    };
+
+    let mut v = SwapVec::with_config(train());
+    v.consume(records().take(500)).unwrap();
+    // Batches are kept in memory until there are enough to train
+    assert!(!v.written_to_file());
+    v.consume(records().skip(500)).unwrap();
+    assert!(v.written_to_file());
+    assert_eq!(v.get(4000).unwrap(), Some(&records().nth(4000).unwrap()));
+    v.persist(&path).unwrap();
+
+    let info = SwapFileInfo::from_path(&path).unwrap();
+    assert_eq!(info.elements, 5000);
+    assert!(info.dictionary_size > 0);
+
+    // The dictionary is read from the file
+    let read_back: Vec<(String, u64)> = SwapVecIter::open(&path, train())
+        .unwrap()
+        .map(|x| x.unwrap())
+        .collect();
+    assert_eq!(read_back, records().collect::<Vec<_>>());
+
+    let mut v: SwapVec<(String, u64)> = SwapVec::open(&path, train()).unwrap();
+    v.consume(records()).unwrap();
+    let read_back: Vec<(String, u64)> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back.len(), 10000);
+    assert_eq!(read_back[5000..], records().collect::<Vec<_>>());
+}
+
+#[test]
+fn too_little_data_to_train() {
+    let mut v = SwapVec::with_config(config(
+        CompressionLevel::Default,
+        ZstdDictionary::Train {
+            batches: 1000,
+            max_size: 4096,
+        },
+    ));
+    v.consume(records().take(100)).unwrap();
+    let read_back: Vec<(String, u64)> = v.into_iter().map(|x| x.unwrap()).collect();
+    assert_eq!(read_back, records().take(100).collect::<Vec<_>>());
+}